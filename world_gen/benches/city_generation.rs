@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use world_gen::city_generation::{CityConfig, CityGenerator};
+
+fn city_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("city_generation");
+    group.sample_size(10);
+    for buildings in [1_000, 10_000] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(buildings),
+            &buildings,
+            |b, &buildings| {
+                b.iter(|| {
+                    let mut city_gen = CityGenerator::new(
+                        CityConfig::default()
+                            .set_seed(1)
+                            .set_buildings(buildings)
+                            .set_important_buildings(6),
+                    );
+                    city_gen.generate().unwrap();
+                    city_gen
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, city_generation);
+criterion_main!(benches);
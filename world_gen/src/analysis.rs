@@ -0,0 +1,553 @@
+//! Maps derived from the heights and biomes of a region: slope, relief, contours and distance
+//! to water
+//!
+//! Unlike the noise layers, these depend on the neighbours of the cells, so the whole region is
+//! kept in memory, with a margin of one cell for the borders.
+
+use clap::ValueEnum;
+use image::{ImageBuffer, RgbImage};
+use rayon::prelude::*;
+use std::fmt;
+
+use crate::{biome::BiomeType, region::Region, WorldGen};
+
+const WATER_COLOR: [u8; 3] = [40, 90, 200];
+const CONTOUR_COLOR: [u8; 3] = [190, 150, 110];
+const INDEX_CONTOUR_COLOR: [u8; 3] = [110, 70, 40];
+/// Every this many contour lines, one is darker
+const INDEX_CONTOUR_EVERY: i64 = 5;
+
+/// Map derived from the heights and biomes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AnalysisLayer {
+    /// Steepness, black when flat and white at the steepest cell of the region
+    Slope,
+    /// Relief lit from the north west
+    Hillshade,
+    /// Lines of equal height, the coastline in blue
+    Contours,
+    /// Distance to the closest water cell, darker the farther
+    WaterDistance,
+    /// Biome colours shaded by the relief
+    Atlas,
+}
+
+impl AnalysisLayer {
+    pub const ALL: [Self; 5] = [
+        Self::Slope,
+        Self::Hillshade,
+        Self::Contours,
+        Self::WaterDistance,
+        Self::Atlas,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Slope => "slope",
+            Self::Hillshade => "hillshade",
+            Self::Contours => "contours",
+            Self::WaterDistance => "water_distance",
+            Self::Atlas => "atlas",
+        }
+    }
+}
+
+/// Parameters of the analysis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnalysisSettings {
+    /// Cells of elevation for a height of 1, the heights being small compared to the cells
+    pub height_scale: f64,
+    /// Direction of the sun in degrees, clockwise from the north
+    pub sun_azimuth: f64,
+    /// Angle of the sun above the horizon in degrees
+    pub sun_elevation: f64,
+    /// Height between two contour lines, positive
+    pub contour_interval: f64,
+}
+
+/// Reasons why the analysis settings are invalid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnalysisError {
+    /// The contour interval is not a positive number
+    InvalidContourInterval(f64),
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidContourInterval(interval) => {
+                write!(f, "contour interval {interval} is not positive")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+impl Default for AnalysisSettings {
+    fn default() -> Self {
+        Self {
+            height_scale: 100.,
+            sun_azimuth: 315.,
+            sun_elevation: 45.,
+            contour_interval: 0.1,
+        }
+    }
+}
+
+impl AnalysisSettings {
+    #[must_use]
+    pub fn set_height_scale(self, height_scale: f64) -> Self {
+        Self {
+            height_scale,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_sun(self, azimuth: f64, elevation: f64) -> Self {
+        Self {
+            sun_azimuth: azimuth,
+            sun_elevation: elevation,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_contour_interval(self, contour_interval: f64) -> Self {
+        Self {
+            contour_interval,
+            ..self
+        }
+    }
+
+    /// Check that the maps can be drawn with these settings
+    ///
+    /// # Errors
+    ///
+    /// Returns the first invalid setting found.
+    pub fn validate(&self) -> Result<(), AnalysisError> {
+        if self.contour_interval > 0. && self.contour_interval.is_finite() {
+            Ok(())
+        } else {
+            Err(AnalysisError::InvalidContourInterval(self.contour_interval))
+        }
+    }
+}
+
+/// Heights and biomes of a region and of the cells around it
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub region: Region,
+    pub settings: AnalysisSettings,
+    /// Row by row with the northmost row first, one more cell on each side than the region
+    heights: Vec<f64>,
+    biomes: Vec<BiomeType>,
+    /// Analytic gradients of the heights towards x and y, not scaled
+    gradients: Vec<(f64, f64)>,
+}
+
+impl Analysis {
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn generate(world_gen: &WorldGen, region: Region, settings: AnalysisSettings) -> Self {
+        let margin = Region::new(
+            region.x - 1,
+            region.y - 1,
+            region.width + 2,
+            region.height + 2,
+        );
+        let (heights, (biomes, gradients)) = (0..margin.len())
+            .into_par_iter()
+            .map(|i| {
+                let (cell, gradient) = world_gen.generate_cell_with_gradient(margin.cell(i));
+                (cell.height, (cell.biome, gradient))
+            })
+            .unzip();
+        Self {
+            region,
+            settings,
+            heights,
+            biomes,
+            gradients,
+        }
+    }
+
+    /// Index of a cell of the region, `column` and `row` can be one cell outside of it
+    #[allow(clippy::cast_sign_loss)]
+    const fn index(&self, column: isize, row: isize) -> usize {
+        (row + 1) as usize * (self.region.width + 2) + (column + 1) as usize
+    }
+
+    /// Cells of the region as column and row, north first
+    #[allow(clippy::cast_possible_wrap)]
+    fn cells(&self) -> impl IndexedParallelIterator<Item = (isize, isize)> + '_ {
+        let width = self.region.width;
+        (0..self.region.len())
+            .into_par_iter()
+            .map(move |i| ((i % width) as isize, (i / width) as isize))
+    }
+
+    fn height(&self, column: isize, row: isize) -> f64 {
+        self.heights[self.index(column, row)]
+    }
+
+    fn is_water(&self, column: isize, row: isize) -> bool {
+        self.biomes[self.index(column, row)].is_water()
+    }
+
+    /// Elevation gained per cell towards the east and the north, from the exact gradient of the
+    /// noise rather than differences with the neighbours
+    fn gradient(&self, column: isize, row: isize) -> (f64, f64) {
+        let (dx, dy) = self.gradients[self.index(column, row)];
+        let scale = self.settings.height_scale;
+        (dx * scale, dy * scale)
+    }
+
+    /// Slope of each cell in degrees, north first
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn slope(&self) -> Vec<f32> {
+        self.cells()
+            .map(|(column, row)| {
+                let (east, north) = self.gradient(column, row);
+                east.hypot(north).atan().to_degrees() as f32
+            })
+            .collect()
+    }
+
+    /// Light received by each cell from 0 in the shadow to 1 facing the sun, north first
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn hillshade(&self) -> Vec<f32> {
+        let (azimuth, elevation) = (
+            self.settings.sun_azimuth.to_radians(),
+            self.settings.sun_elevation.to_radians(),
+        );
+        // Towards the sun, x to the east, y to the north and z up
+        let sun = (
+            elevation.cos() * azimuth.sin(),
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+        );
+        self.cells()
+            .map(|(column, row)| {
+                let (east, north) = self.gradient(column, row);
+                let normal = (-east, -north, 1.);
+                let length = (east * east + north * north + 1.).sqrt();
+                let light = (normal.0 * sun.0 + normal.1 * sun.1 + normal.2 * sun.2) / length;
+                light.max(0.) as f32
+            })
+            .collect()
+    }
+
+    /// Biome of each cell, north first
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn biomes(&self) -> Vec<BiomeType> {
+        self.cells()
+            .map(|(column, row)| self.biomes[self.index(column, row)])
+            .collect()
+    }
+
+    /// Distance of each cell to the closest water cell of the region, north first
+    ///
+    /// Water outside of the region is ignored, so cells close to the borders may be closer to
+    /// water than reported. The distance is infinite if there is no water at all.
+    #[must_use]
+    pub fn water_distance(&self) -> Vec<f32> {
+        let water = self
+            .cells()
+            .map(|(column, row)| self.is_water(column, row))
+            .collect::<Vec<_>>();
+        self.distances(&water)
+    }
+
+    /// Distance of each cell to the closest target cell, the targets being given north first
+    ///
+    /// The distance is infinite if there is no target at all.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn distances(&self, targets: &[bool]) -> Vec<f32> {
+        let (width, height) = self.region.size();
+        // Exact euclidean distance transform, on the columns then on the rows
+        let columns = (0..width)
+            .into_par_iter()
+            .map(|column| {
+                let targets = (0..height)
+                    .map(|row| {
+                        if targets[row * width + column] {
+                            0.
+                        } else {
+                            f64::INFINITY
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                squared_distances(&targets)
+            })
+            .collect::<Vec<_>>();
+        (0..height)
+            .into_par_iter()
+            .flat_map_iter(|row| {
+                let row = columns.iter().map(|column| column[row]).collect::<Vec<_>>();
+                squared_distances(&row).into_iter().map(|d| d.sqrt() as f32)
+            })
+            .collect()
+    }
+
+    /// Image of a layer, one pixel per cell with north up
+    ///
+    /// # Panics
+    ///
+    /// Panics on contours if the settings aren't valid, see [`AnalysisSettings::validate`].
+    #[must_use]
+    pub fn image(&self, layer: AnalysisLayer) -> RgbImage {
+        match layer {
+            AnalysisLayer::Slope => self.slope_image(),
+            AnalysisLayer::Hillshade => self.hillshade_image(),
+            AnalysisLayer::Contours => self.contour_image(),
+            AnalysisLayer::WaterDistance => self.water_distance_image(),
+            AnalysisLayer::Atlas => self.atlas_image(),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn image_from(&self, pixels: impl IndexedParallelIterator<Item = [u8; 3]>) -> RgbImage {
+        let pixels = pixels.flatten_iter().collect();
+        ImageBuffer::from_raw(self.region.width as u32, self.region.height as u32, pixels)
+            .expect("one pixel per cell")
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn slope_image(&self) -> RgbImage {
+        let slope = self.slope();
+        let max = slope.iter().copied().fold(f32::EPSILON, f32::max);
+        self.image_from(slope.into_par_iter().map(|s| [(s / max * 255.) as u8; 3]))
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn hillshade_image(&self) -> RgbImage {
+        self.image_from(
+            self.hillshade()
+                .into_par_iter()
+                .map(|light| [(light * 255.) as u8; 3]),
+        )
+    }
+
+    /// Cells where the height crosses a multiple of the interval with their east or south
+    /// neighbour, on white, and the coastline where the water ends
+    #[allow(clippy::cast_possible_truncation)]
+    fn contour_image(&self) -> RgbImage {
+        if let Err(error) = self.settings.validate() {
+            panic!("{error}");
+        }
+        let interval = self.settings.contour_interval;
+        let level = |column, row| (self.height(column, row) / interval).floor() as i64;
+        self.image_from(self.cells().map(|(column, row)| {
+            let water = self.is_water(column, row);
+            if [(column + 1, row), (column, row + 1)]
+                .into_iter()
+                .any(|(column, row)| self.is_water(column, row) != water)
+            {
+                return WATER_COLOR;
+            }
+            let here = level(column, row);
+            let line = [level(column + 1, row), level(column, row + 1)]
+                .into_iter()
+                .filter(|other| *other != here)
+                // The level of the line between two cells is the higher one
+                .map(|other| other.max(here))
+                .max();
+            match line {
+                None => [255; 3],
+                Some(line) if line % INDEX_CONTOUR_EVERY == 0 => INDEX_CONTOUR_COLOR,
+                Some(_) => CONTOUR_COLOR,
+            }
+        }))
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn water_distance_image(&self) -> RgbImage {
+        let distances = self.water_distance();
+        let max = distances
+            .iter()
+            .copied()
+            .filter(|d| d.is_finite())
+            .fold(1., f32::max);
+        self.image_from(distances.into_par_iter().map(|d| {
+            if d == 0. {
+                WATER_COLOR
+            } else {
+                [(255. * (1. - d.min(max) / max)) as u8; 3]
+            }
+        }))
+    }
+
+    /// Biome colours multiplied by the hillshade relative to flat ground, water isn't shaded
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn atlas_image(&self) -> RgbImage {
+        let hillshade = self.hillshade();
+        let flat = self.settings.sun_elevation.to_radians().sin() as f32;
+        self.image_from(self.cells().map(|(column, row)| {
+            let color = self.biomes[self.index(column, row)].color();
+            if self.is_water(column, row) {
+                return color;
+            }
+            let light = hillshade[row as usize * self.region.width + column as usize];
+            let factor = (light / flat).clamp(0., 1.5);
+            color.map(|c| (f32::from(c) * factor).min(255.) as u8)
+        }))
+    }
+}
+
+/// Squared distance of each value to the closest zero, the others being infinite
+///
+/// One dimension of the distance transform of Felzenszwalb and Huttenlocher, `values` being the
+/// squared distances found along the other dimension.
+#[allow(clippy::cast_precision_loss)]
+fn squared_distances(values: &[f64]) -> Vec<f64> {
+    let n = values.len();
+    let mut distances = vec![f64::INFINITY; n];
+    // Parabolas of the lower envelope and where they start
+    let mut vertices = Vec::with_capacity(n);
+    let mut starts: Vec<f64> = Vec::with_capacity(n + 1);
+    for (q, value) in values.iter().enumerate() {
+        if value.is_infinite() {
+            continue;
+        }
+        let intersection = |p: usize| {
+            let (p_f, q_f) = (p as f64, q as f64);
+            ((value + q_f * q_f) - (values[p] + p_f * p_f)) / (2. * (q_f - p_f))
+        };
+        while let Some(&p) = vertices.last() {
+            if intersection(p) <= starts[starts.len() - 1] {
+                vertices.pop();
+                starts.pop();
+            } else {
+                break;
+            }
+        }
+        let start = vertices
+            .last()
+            .map_or(f64::NEG_INFINITY, |&p| intersection(p));
+        vertices.push(q);
+        starts.push(start);
+    }
+    if vertices.is_empty() {
+        return distances;
+    }
+    let mut k = 0;
+    for (q, distance) in distances.iter_mut().enumerate() {
+        while k + 1 < vertices.len() && starts[k + 1] < q as f64 {
+            k += 1;
+        }
+        let p = vertices[k];
+        let offset = q as f64 - p as f64;
+        *distance = offset * offset + values[p];
+    }
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        squared_distances, Analysis, AnalysisError, AnalysisLayer, AnalysisSettings, WATER_COLOR,
+    };
+    use crate::{region::Region, WorldGen};
+
+    #[test]
+    fn test_squared_distances() {
+        let inf = f64::INFINITY;
+        assert_eq!(
+            squared_distances(&[inf, 0., inf, inf, inf, 0.]),
+            [1., 0., 1., 4., 1., 0.]
+        );
+        assert_eq!(squared_distances(&[inf, 2., inf]), [3., 2., 3.]);
+        assert!(squared_distances(&[inf; 3]).iter().all(|d| d.is_infinite()));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_wrap, clippy::cast_precision_loss)]
+    fn test_water_distance() {
+        let world_gen = WorldGen::new(1., Some(2));
+        let region = Region::new(-60, -40, 120, 80);
+        let analysis = Analysis::generate(&world_gen, region, AnalysisSettings::default());
+        let distances = analysis.water_distance();
+        let water = (0..region.len())
+            .filter(|i| world_gen.generate_cell(region.cell(*i)).biome.is_water())
+            .map(|i| region.cell(i))
+            .collect::<Vec<_>>();
+        assert!(!water.is_empty() && water.len() < region.len());
+        // Brute force on a few cells
+        for i in (0..region.len()).step_by(97) {
+            let (x, y) = region.cell(i);
+            let expected = water
+                .iter()
+                .map(|(wx, wy)| (((wx - x).pow(2) + (wy - y).pow(2)) as f64).sqrt())
+                .fold(f64::INFINITY, f64::min);
+            assert!((f64::from(distances[i]) - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_layers() {
+        let world_gen = WorldGen::new(1., Some(2));
+        let region = Region::new(-30, 10, 40, 20);
+        let analysis = Analysis::generate(&world_gen, region, AnalysisSettings::default());
+        let slope = analysis.slope();
+        assert_eq!(slope.len(), region.len());
+        assert!(slope.iter().all(|s| (0. ..90.).contains(s)));
+        assert!(analysis
+            .hillshade()
+            .iter()
+            .all(|light| (0. ..=1.).contains(light)));
+        for layer in AnalysisLayer::ALL {
+            assert_eq!(analysis.image(layer).dimensions(), (40, 20));
+        }
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_wrap)]
+    fn test_contour_coastline() {
+        let world_gen = WorldGen::new(1., Some(2));
+        let region = Region::new(-60, -40, 120, 80);
+        let analysis = Analysis::generate(&world_gen, region, AnalysisSettings::default());
+        let image = analysis.image(AnalysisLayer::Contours);
+        let mut coast = 0;
+        for (column, row, pixel) in image.enumerate_pixels() {
+            let (column, row) = (column as isize, row as isize);
+            let water = analysis.is_water(column, row);
+            // The coastline follows the biomes, not the height 0
+            let expected = analysis.is_water(column + 1, row) != water
+                || analysis.is_water(column, row + 1) != water;
+            assert_eq!(pixel.0 == WATER_COLOR, expected);
+            coast += usize::from(expected);
+        }
+        assert!(coast > 0);
+    }
+
+    #[test]
+    fn test_invalid_contour_interval() {
+        assert_eq!(AnalysisSettings::default().validate(), Ok(()));
+        for interval in [0., -0.1, f64::NAN] {
+            let settings = AnalysisSettings::default().set_contour_interval(interval);
+            assert!(matches!(
+                settings.validate(),
+                Err(AnalysisError::InvalidContourInterval(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_hillshade_faces_the_sun() {
+        let world_gen = WorldGen::new(1., Some(2));
+        let region = Region::new(3, 0, 1, 1);
+        let light = |azimuth| {
+            let settings = AnalysisSettings::default().set_sun(azimuth, 45.);
+            Analysis::generate(&world_gen, region, settings).hillshade()[0]
+        };
+        let (_, north) =
+            Analysis::generate(&world_gen, region, AnalysisSettings::default()).gradient(0, 0);
+        // Lit more by the sun in the north if the ground goes down towards it
+        assert_eq!(light(0.) > light(180.), north < 0.);
+    }
+}
@@ -1,110 +1,245 @@
 use clap::Parser;
-use image::{ImageBuffer, ImageResult, Rgb};
+use image::{
+    imageops::{resize, FilterType},
+    ImageResult, Rgb,
+};
 use progressing::{mapping, Baring};
-use world_gen::{cell::Cell, image_utils::draw_rect, WorldGen};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+use world_gen::{
+    analysis::{Analysis, AnalysisLayer, AnalysisSettings},
+    cell::Cell,
+    city_generation::CityGenerator,
+    geojson::biome_geojson,
+    heightmap::{Heightmap, HeightmapFormat},
+    mesh::{MeshFormat, MeshSettings, TerrainMesh},
+    region::Region,
+    render::{stream_region_png, CellColor, Layer},
+    spawn::SpawnCriteria,
+    tiled::{biome_tileset_image, tiled_map},
+    WorldGen,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    #[arg(short, long)]
-    width: i64,
+    /// Width of the map centered on the origin, in cells
+    #[arg(short, long, required_unless_present = "region")]
+    width: Option<usize>,
+    /// Height of the map centered on the origin, the width by default
+    #[arg(long, requires = "width")]
+    height: Option<usize>,
+    /// Cells of the map as `x,y,width,height`, x and y being the south west corner
+    #[arg(long, conflicts_with = "width", allow_hyphen_values = true)]
+    region: Option<Region>,
+    /// Size of a cell in the images, in pixels
+    #[arg(long, default_value_t = 1)]
+    zoom: u32,
     #[arg(short, long)]
     scale: f64,
+    /// Also write the slope map
+    #[arg(long)]
+    slope: bool,
+    /// Also write the relief lit from the north west
+    #[arg(long)]
+    hillshade: bool,
+    /// Also write contour lines separated by this positive height, 0.1 by default
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "0.1",
+        value_parser = parse_contour_interval
+    )]
+    contours: Option<f64>,
+    /// Also write the distance to the closest water
+    #[arg(long)]
+    water_distance: bool,
+    /// Also write the biome map shaded by the relief
+    #[arg(long)]
+    atlas: bool,
+    /// Also export the biome regions as GeoJSON
+    #[arg(long)]
+    geojson: bool,
+    /// Also export the biomes as a Tiled map
+    #[arg(long)]
+    tiled: bool,
+    /// JSON configuration of a city to add to the Tiled map
+    #[arg(long, requires = "tiled")]
+    city_config: Option<PathBuf>,
+    /// Also export the heights in these formats, with their metadata
+    #[arg(long, value_enum, value_delimiter = ',')]
+    heightmap: Vec<HeightmapFormat>,
+    /// Also export the terrain as a mesh in this format
+    #[arg(long, value_enum)]
+    mesh: Option<MeshFormat>,
+    /// Depth of the skirts around the mesh
+    #[arg(long, requires = "mesh")]
+    skirt: Option<f32>,
 }
 
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-fn save_intermediate_maps(size: (u32, u32), cells: &[Vec<Cell>]) {
-    let mut temp_img = ImageBuffer::new(size.0, size.1);
-    let mut moisture_img = ImageBuffer::new(size.0, size.1);
-    let mut continentalness_img = ImageBuffer::new(size.0, size.1);
-    let mut erosion_img = ImageBuffer::new(size.0, size.1);
-
-    for (x, cell_row) in cells.iter().enumerate() {
-        for (y, cell) in cell_row.iter().rev().enumerate() {
-            let temp_color = (255. * (cell.temp + 1.) / 2.) as u8;
-            // warm = red, cold = blue
-            let color = Rgb([temp_color, 0, 255 - temp_color]);
-            temp_img.put_pixel(x as u32, y as u32, color);
-
-            let moisture_color = (255. * (cell.moisture + 1.) / 2.) as u8;
-            // wet = blue, dry = red
-            let color = Rgb([moisture_color, 0, 255 - moisture_color]);
-            moisture_img.put_pixel(x as u32, y as u32, color);
-
-            let continentalness_color = (255. * (cell.continentalness + 1.) / 2.) as u8;
-            // black = terrain, white = ocean
-            let color = Rgb([
-                continentalness_color,
-                continentalness_color,
-                continentalness_color,
-            ]);
-            continentalness_img.put_pixel(x as u32, y as u32, color);
+impl Cli {
+    /// Layers selected by the flags
+    fn analysis_layers(&self) -> Vec<AnalysisLayer> {
+        [
+            (self.slope, AnalysisLayer::Slope),
+            (self.hillshade, AnalysisLayer::Hillshade),
+            (self.contours.is_some(), AnalysisLayer::Contours),
+            (self.water_distance, AnalysisLayer::WaterDistance),
+            (self.atlas, AnalysisLayer::Atlas),
+        ]
+        .into_iter()
+        .filter_map(|(selected, layer)| selected.then_some(layer))
+        .collect()
+    }
 
-            let erosion_color = (255. * (cell.erosion + 1.) / 2.) as u8;
-            // white = high erosion, black = low erosion
+    fn region(&self) -> Region {
+        self.region.unwrap_or_else(|| {
+            let width = self.width.unwrap_or_default();
+            Region::centered(width, self.height.unwrap_or(width))
+        })
+    }
+}
 
-            let color = Rgb([erosion_color, erosion_color, erosion_color]);
+/// Write the biome map and the noise maps, streamed so that the region can be very large
+#[allow(clippy::cast_possible_wrap)]
+fn save_maps(cli: &Cli, world_gen: &WorldGen, region: Region) -> ImageResult<()> {
+    let mut progress_bar = mapping::Bar::with_range(0, region.height as i64).timed();
+    progress_bar.set_len(20);
 
-            erosion_img.put_pixel(x as u32, y as u32, color);
+    // Red square on the spawn, the center of the map if there is no good cell
+    let (spawn_x, spawn_y) = world_gen
+        .find_spawn(region.center(), &SpawnCriteria::default())
+        .unwrap_or_else(|| region.center());
+    let biome_color = |(x, y): (isize, isize), cell: &Cell| {
+        if (spawn_x - 1..=spawn_x).contains(&x) && (spawn_y - 1..=spawn_y).contains(&y) {
+            Rgb([255, 0, 0])
+        } else {
+            Rgb(cell.biome.color())
         }
+    };
+    let layer_colors = Layer::ALL.map(|layer| move |_, cell: &Cell| layer.color(cell));
+    let mut outputs: Vec<(_, CellColor)> = vec![(
+        BufWriter::new(File::create("output/biome_map.png")?),
+        &biome_color,
+    )];
+    for (layer, color) in Layer::ALL.iter().zip(&layer_colors) {
+        let path = format!("output/{}_map.png", layer.name());
+        outputs.push((BufWriter::new(File::create(path)?), color));
     }
-
-    temp_img.save("output/temperature_map.png").unwrap();
-    moisture_img.save("output/moisture_map.png").unwrap();
-    continentalness_img
-        .save("output/continentalness_map.png")
-        .unwrap();
-    erosion_img.save("output/erosion_map.png").unwrap();
+    stream_region_png(world_gen, region, cli.zoom, outputs, |rows| {
+        progress_bar.set(rows as i64);
+        print!("\r{progress_bar}");
+    })?;
+    println!();
+    Ok(())
 }
 
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-fn save_biome_map(biome_img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, cells: &[Vec<Cell>]) {
-    for (x, cell_row) in cells.iter().enumerate() {
-        for (y, cell) in cell_row.iter().rev().enumerate() {
-            let color = Rgb(cell.biome.color());
-            biome_img.put_pixel(x as u32, y as u32, color);
+/// Write the maps derived from the heights, the whole region is in memory
+#[allow(clippy::cast_possible_truncation)]
+fn save_analysis_maps(cli: &Cli, world_gen: &WorldGen, region: Region) -> ImageResult<()> {
+    let layers = cli.analysis_layers();
+    if layers.is_empty() {
+        return Ok(());
+    }
+    let mut settings = AnalysisSettings::default();
+    if let Some(interval) = cli.contours {
+        settings = settings.set_contour_interval(interval);
+    }
+    let analysis = Analysis::generate(world_gen, region, settings);
+    for layer in layers {
+        let mut image = analysis.image(layer);
+        if cli.zoom > 1 {
+            image = resize(
+                &image,
+                image.width() * cli.zoom,
+                image.height() * cli.zoom,
+                FilterType::Nearest,
+            );
         }
+        image.save(format!("output/{}_map.png", layer.name()))?;
     }
+    Ok(())
 }
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-fn biome_generator(cli: &Cli) -> ImageResult<()> {
-    let width = cli.width;
-    let scale = cli.scale;
-    let height = width;
-    let mut progress_bar = mapping::Bar::with_range(0, height * width).timed();
-    progress_bar.set_len(20);
-
-    let world_gen = WorldGen::new(scale, None);
-    let cells = (-width / 2..width / 2)
-        .map(|x| {
-            progress_bar.set((x + width / 2) * height);
 
-            print!("\r{progress_bar}");
-            (-height / 2..height / 2)
-                .map(|y| world_gen.generate_cell((x.try_into().unwrap(), y.try_into().unwrap())))
-                .collect::<Vec<_>>()
-        })
-        .collect::<Vec<_>>();
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn biome_generator(cli: &Cli) -> ImageResult<()> {
+    let region = cli.region();
+    let world_gen = WorldGen::new(cli.scale, None);
+    save_maps(cli, &world_gen, region)?;
+    save_analysis_maps(cli, &world_gen, region)?;
 
-    // Noise maps
-    save_intermediate_maps((width as u32, height as u32), &cells);
+    if cli.geojson {
+        let geojson = biome_geojson(&world_gen, region.origin(), region.size());
+        serde_json::to_writer(File::create("output/biomes.geojson")?, &geojson)
+            .map_err(std::io::Error::from)?;
+    }
+    if cli.tiled {
+        save_tiled_map(cli, &world_gen, region)?;
+    }
+    if !cli.heightmap.is_empty() {
+        let heightmap = Heightmap::generate(&world_gen, region.origin(), region.size());
+        for format in &cli.heightmap {
+            heightmap.save(format!("output/heightmap.{}", format.extension()), *format)?;
+        }
+        serde_json::to_writer_pretty(
+            File::create("output/heightmap.json")?,
+            &heightmap.metadata(&cli.heightmap),
+        )
+        .map_err(std::io::Error::from)?;
+    }
+    if let Some(format) = cli.mesh {
+        let settings = MeshSettings::default()
+            .set_origin([region.x as i32, region.y as i32])
+            .set_size([region.width as u32, region.height as u32])
+            .set_skirt_depth(cli.skirt);
+        TerrainMesh::generate(&world_gen, &settings).write(
+            &mut File::create(format!("output/terrain.{}", format.extension()))?,
+            format,
+        )?;
+    }
+    Ok(())
+}
 
-    // Biome map
-    let mut biome_img = ImageBuffer::new(width as u32, height as u32);
-    save_biome_map(&mut biome_img, &cells);
-    draw_rect(
-        &mut biome_img,
-        (width as u32 / 2 - 1, height as u32 / 2 - 1),
-        1,
-        1,
-        Rgb([255, 0, 0]),
+/// Save the biomes and the city, if any, as a Tiled map with its tileset
+fn save_tiled_map(cli: &Cli, world_gen: &WorldGen, region: Region) -> ImageResult<()> {
+    let city_gen = match &cli.city_config {
+        Some(path) => {
+            let config =
+                serde_json::from_str(&fs::read_to_string(path)?).map_err(std::io::Error::from)?;
+            let mut city_gen = CityGenerator::new(config);
+            city_gen.generate().map_err(std::io::Error::other)?;
+            Some(city_gen)
+        }
+        None => None,
+    };
+    biome_tileset_image().save("output/biome_tileset.png")?;
+    let map = tiled_map(
+        world_gen,
+        cli.scale,
+        region.origin(),
+        region.size(),
+        city_gen.as_ref(),
+        "biome_tileset.png",
     );
-    biome_img.save("output/biome_map.png")
+    serde_json::to_writer(File::create("output/map.tmj")?, &map).map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+/// Parse a contour interval, rejecting the ones the contours can't be drawn with
+fn parse_contour_interval(s: &str) -> Result<f64, String> {
+    let interval = s.trim().parse::<f64>().map_err(|e| format!("{s}: {e}"))?;
+    AnalysisSettings::default()
+        .set_contour_interval(interval)
+        .validate()
+        .map_err(|e| e.to_string())?;
+    Ok(interval)
 }
 
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 fn main() -> ImageResult<()> {
     let cli = Cli::parse();
-    std::fs::create_dir("output").unwrap_or_default();
+    fs::create_dir("output").unwrap_or_default();
     biome_generator(&cli)
 }
@@ -1,98 +1,199 @@
-use std::fs;
+use std::{
+    fs::{self, File},
+    ops::Range,
+    path::PathBuf,
+};
 
-use clap::Parser;
-use image::{ImageBuffer, ImageResult, Rgb};
-use world_gen::{city_generation::CityGenerator, image_utils::draw_rect};
+use clap::{Parser, ValueEnum};
+use image::ImageResult;
+use world_gen::{
+    city_generation::{CityConfig, CityGenerator, LayoutMode},
+    geojson::city_geojson,
+    population::PopulationGenerator,
+    render::city_image,
+    svg::city_svg,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
+    /// JSON file with the configuration of the city, the other options override it
+    #[arg(long)]
+    config: Option<PathBuf>,
     /// Number of buildings
     #[arg(short, long)]
-    buildings: usize,
+    buildings: Option<usize>,
     /// Number of important buildings
     #[arg(short, long)]
-    important_buildings: usize,
+    important_buildings: Option<usize>,
     /// Maximum distance between important buildings
     #[arg(short, long)]
-    max_distance_seeds: i32,
+    max_distance_seeds: Option<i32>,
     /// Scale of the important buildings
     #[arg(short, long)]
-    scale_seeds: i32,
+    scale_seeds: Option<i32>,
     /// Seed
     #[arg(long)]
-    seed: u64,
+    seed: Option<u64>,
+    /// Min and max width of the buildings, as `min..max`
+    #[arg(long, value_parser = parse_range)]
+    width: Option<Range<i32>>,
+    /// Min and max height of the buildings, as `min..max`
+    #[arg(long, value_parser = parse_range)]
+    height: Option<Range<i32>>,
+    /// Min and max distance between buildings, as `min..max`
+    #[arg(long, value_parser = parse_range)]
+    distance: Option<Range<i32>>,
+    /// Place the buildings or the streets first
+    #[arg(long, value_enum)]
+    layout: Option<LayoutMode>,
+    /// With the street first layout, 0 gives organic streets and 1 a grid
+    #[arg(long)]
+    grid_bias: Option<f64>,
+    /// Place the buildings on lots subdividing the blocks enclosed by roads
+    #[arg(long)]
+    blocks: bool,
+    /// Also generate the inhabitants of the city
+    #[arg(long)]
+    population: bool,
+    /// Surround the city with a wall this far from the buildings
+    #[arg(long)]
+    wall_padding: Option<i32>,
+    /// Also export the city as GeoJSON
+    #[arg(long)]
+    geojson: bool,
+    /// Format of the rendered city
+    #[arg(long, value_enum, default_value_t = Format::Png)]
+    format: Format,
+    /// Number of growth stages to simulate after the generation, each one is saved
+    #[arg(long, default_value_t = 0)]
+    stages: u32,
+    /// Number of years between two growth stages
+    #[arg(long, default_value_t = 10)]
+    years_per_stage: u32,
+}
+
+/// How the city is rendered
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// Raster image, one pixel per cell
+    Png,
+    /// Vector image with a legend
+    Svg,
+}
+
+/// Parse a range written as `min..max`
+fn parse_range(s: &str) -> Result<Range<i32>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("expected min..max, got {s}"))?;
+    let parse = |n: &str| n.trim().parse::<i32>().map_err(|e| e.to_string());
+    Ok(parse(start)?..parse(end)?)
+}
+
+/// Configuration from the file, if any, with the options of the command line on top of it
+fn city_config(cli: &Cli) -> std::io::Result<CityConfig> {
+    let mut config = match &cli.config {
+        Some(path) => serde_json::from_str(&fs::read_to_string(path)?)?,
+        None => CityConfig::default(),
+    };
+    if let Some(buildings) = cli.buildings {
+        config = config.set_buildings(buildings);
+    }
+    if let Some(important_buildings) = cli.important_buildings {
+        config = config.set_important_buildings(important_buildings);
+    }
+    if let Some(max_distance) = cli.max_distance_seeds {
+        config = config.set_important_buildings_max_distance(max_distance);
+    }
+    if let Some(scale) = cli.scale_seeds {
+        config = config.set_important_building_scale(scale);
+    }
+    if let Some(seed) = cli.seed {
+        config = config.set_seed(seed);
+    }
+    if let Some(width) = &cli.width {
+        config = config.set_width_bound(width.clone());
+    }
+    if let Some(height) = &cli.height {
+        config = config.set_height_bound(height.clone());
+    }
+    if let Some(distance) = &cli.distance {
+        config = config.set_distance_bound(distance.clone());
+    }
+    if let Some(layout) = cli.layout {
+        config = config.set_layout(layout);
+    }
+    if let Some(grid_bias) = cli.grid_bias {
+        config = config.set_grid_bias(grid_bias);
+    }
+    if cli.blocks {
+        config = config.set_subdivide_blocks(true);
+    }
+    Ok(config)
 }
 
 #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 fn city_generator(cli: &Cli) -> ImageResult<()> {
-    let buildings = cli.buildings;
-    let important_buildings = cli.important_buildings;
-    let important_buildings_max_distance = cli.max_distance_seeds;
-    let important_buildings_scale = cli.scale_seeds;
-    let seed = cli.seed;
+    let config = city_config(cli)?;
+    let seed = config.seed;
+    // Keep the configuration next to the result to generate it again
+    serde_json::to_writer_pretty(File::create("output/city_config.json")?, &config)
+        .map_err(std::io::Error::from)?;
 
-    let mut city_gen = CityGenerator::new(
-        seed,
-        8..30,
-        8..30,
-        20..100,
-        important_buildings_max_distance,
-    );
-
-    city_gen.generate(buildings, important_buildings, important_buildings_scale);
+    let mut city_gen = CityGenerator::new(config);
+    city_gen.generate().map_err(std::io::Error::other)?;
+    if let Some(padding) = cli.wall_padding {
+        city_gen.generate_wall(padding);
+    }
 
     // city_gen.generate_roads_astar();
-    let mut img = ImageBuffer::new(
-        10 + (city_gen.max_x - city_gen.min_x) as u32,
-        10 + (city_gen.max_y - city_gen.min_y) as u32,
-    );
     println!(
         "size : {}x{}",
         city_gen.max_x - city_gen.min_x,
         city_gen.max_y - city_gen.min_y
     );
-
-    // roads
-    for road in &city_gen.roads {
-        for (x, y) in road {
-            img.put_pixel(
-                *x as u32 - city_gen.min_x as u32,
-                *y as u32 - city_gen.min_y as u32,
-                Rgb([139, 69, 19]),
-            );
-        }
-    }
-    for building in city_gen.buildings.values() {
-        draw_rect(
-            &mut img,
-            (
-                building.x as u32 - city_gen.min_x as u32,
-                building.y as u32 - city_gen.min_y as u32,
-            ),
-            building.width as u32,
-            building.height as u32,
-            // color based on id, the less the more red
-            Rgb([
-                255 - (building.id as f32 / buildings as f32 * 255.0) as u8,
-                if building.is_important { 255 } else { 0 },
-                (building.id as f32 / buildings as f32 * 255.0) as u8,
-            ]),
-        );
-        draw_rect(
-            &mut img,
-            (
-                building.door.0 as u32 - city_gen.min_x as u32,
-                building.door.1 as u32 - city_gen.min_y as u32,
-            ),
-            0,
-            0,
-            Rgb([255, 0, 0]),
+    save_city(&city_gen, cli.format, "output/city")?;
+    if cli.geojson {
+        serde_json::to_writer(
+            File::create("output/city.geojson")?,
+            &city_geojson(&city_gen),
+        )
+        .map_err(std::io::Error::from)?;
+    }
+    for _ in 0..cli.stages {
+        city_gen
+            .step(cli.years_per_stage)
+            .map_err(std::io::Error::other)?;
+        println!(
+            "year {} : {} buildings",
+            city_gen.age,
+            city_gen.buildings.len()
         );
+        save_city(
+            &city_gen,
+            cli.format,
+            &format!("output/city_year_{}", city_gen.age),
+        )?;
+    }
+
+    if cli.population {
+        let population = PopulationGenerator::new(seed).generate(&city_gen);
+        println!("population : {}", population.npcs.len());
+        serde_json::to_writer_pretty(File::create("output/population.json")?, &population)
+            .map_err(std::io::Error::from)?;
     }
+    Ok(())
+}
 
-    img.save("output/city.png")
+/// Render the city to `path` with the extension of the format
+fn save_city(city_gen: &CityGenerator, format: Format, path: &str) -> ImageResult<()> {
+    match format {
+        Format::Png => city_image(city_gen).save(format!("{path}.png")),
+        Format::Svg => Ok(fs::write(format!("{path}.svg"), city_svg(city_gen))?),
+    }
 }
+
 #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 fn main() -> ImageResult<()> {
     let cli = Cli::parse();
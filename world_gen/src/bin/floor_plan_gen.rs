@@ -0,0 +1,117 @@
+use std::{error::Error, fs};
+
+use clap::{Parser, ValueEnum};
+use image::{ImageBuffer, Rgb};
+use world_gen::{
+    city_generation::{BuildingType, Corner, Footprint, Side},
+    floor_plan::FloorPlanGenerator,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Shape {
+    Rectangle,
+    L,
+    T,
+    Rotated,
+}
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Width of the building
+    #[arg(short, long)]
+    width: i32,
+    /// Height of the building
+    #[arg(long)]
+    height: i32,
+    /// Shape of the building
+    #[arg(long, default_value = "rectangle")]
+    shape: Shape,
+    /// Type of the building, decides the purpose of the rooms
+    #[arg(short, long, default_value = "house")]
+    building_type: BuildingType,
+    /// Minimum length of a side of a room
+    #[arg(long, default_value_t = 4)]
+    min_room_size: i32,
+    /// Rooms are split until both of their sides are smaller than this
+    #[arg(long, default_value_t = 12)]
+    max_room_size: i32,
+    /// Size of a cell in pixels
+    #[arg(short, long, default_value_t = 8)]
+    pixels_per_cell: u32,
+    /// Seed
+    #[arg(long)]
+    seed: u64,
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn floor_plan_generator(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (cli.width, cli.height);
+    let footprint = match cli.shape {
+        Shape::Rectangle => Footprint::rectangle(0, 0, width, height),
+        Shape::L => Footprint::l_shape(
+            0,
+            0,
+            width,
+            height,
+            (width / 2, height / 2),
+            Corner::NorthEast,
+        ),
+        Shape::T => Footprint::t_shape(0, 0, width, height, height / 2, width / 3, Side::North),
+        Shape::Rotated => {
+            Footprint::rotated_rectangle((f64::from(width), f64::from(height)), width, height, 0.4)
+        }
+    };
+    let (min_x, min_y, max_x, max_y) = footprint.bounding_box();
+    let Some(&entrance) = footprint.door_candidates().first() else {
+        return Err(format!("the {width}x{height} footprint has no wall to put a door on").into());
+    };
+
+    let mut generator = FloorPlanGenerator::new(cli.seed, cli.min_room_size, cli.max_room_size);
+    let floor_plan = generator.generate(&footprint, entrance, cli.building_type);
+    println!("rooms : {}", floor_plan.rooms.len());
+    for room in &floor_plan.rooms {
+        println!(
+            "{:>3} {:?} ({} cells)",
+            room.id,
+            room.purpose,
+            room.cells.len()
+        );
+    }
+
+    let scale = cli.pixels_per_cell;
+    let mut img = ImageBuffer::new(
+        (max_x - min_x + 1) as u32 * scale,
+        (max_y - min_y + 1) as u32 * scale,
+    );
+    let mut fill = |pos: (i32, i32), color: Rgb<u8>| {
+        let (x, y) = ((pos.0 - min_x) as u32, (pos.1 - min_y) as u32);
+        for i in 0..scale {
+            for j in 0..scale {
+                img.put_pixel(x * scale + i, y * scale + j, color);
+            }
+        }
+    };
+
+    for room in &floor_plan.rooms {
+        for pos in &room.cells {
+            fill(*pos, Rgb(room.purpose.color()));
+        }
+    }
+    for pos in &floor_plan.walls {
+        fill(*pos, Rgb([60, 60, 60]));
+    }
+    for door in &floor_plan.doors {
+        fill(door.pos, Rgb([139, 69, 19]));
+    }
+    fill(floor_plan.entrance, Rgb([255, 0, 0]));
+
+    img.save("output/floor_plan.png")?;
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    fs::create_dir("output").unwrap_or_default();
+    floor_plan_generator(&cli)
+}
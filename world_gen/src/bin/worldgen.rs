@@ -0,0 +1,564 @@
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use image::Rgb;
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use world_gen::{
+    analysis::{Analysis, AnalysisLayer, AnalysisSettings},
+    cell::Cell,
+    city_generation::{CityConfig, CityGenerator},
+    geojson::{biome_geojson, city_geojson},
+    heightmap::{Heightmap, HeightmapFormat},
+    mesh::{MeshFormat, MeshSettings, TerrainMesh},
+    population::PopulationGenerator,
+    region::Region,
+    render::{city_image, generate_region, layer_image, stream_region_png, Layer},
+    report::WorldReport,
+    search::{search_seeds, SeedConstraints, SeedMatch},
+    svg::city_svg,
+    tiled::{biome_tileset_image, tiled_map},
+    tiles::TilePyramid,
+    BiomeType, WorldGen,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(
+    name = "worldgen",
+    version,
+    about = "Generate worlds and cities",
+    long_about = None,
+    after_help = "Exit status is 0 on success, 1 if the generation or a file failed and 2 for invalid arguments."
+)]
+struct Cli {
+    #[command(flatten)]
+    common: Common,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Options shared by all the commands
+#[derive(Args)]
+struct Common {
+    /// Seed of the world and of the city, random if given neither here nor in the configuration
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+    /// JSON configuration, the options of the command line override it
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Directory the files are written to, created if needed
+    #[arg(long, global = true, default_value = "output")]
+    out_dir: PathBuf,
+    /// Cells to generate as `x,y,width,height`, x and y being the south west corner
+    #[arg(
+        long,
+        global = true,
+        default_value = "-256,-256,512,512",
+        allow_hyphen_values = true
+    )]
+    region: Region,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Map of the biomes of the region
+    Biomes {
+        #[arg(long, value_enum, default_value_t = BiomeFormat::Png)]
+        format: BiomeFormat,
+    },
+    /// Maps of the noise layers used to pick the biomes, or derived from the heights
+    Layers {
+        /// Layers to write, all the noise layers if no analysis layer is given either
+        #[arg(long, value_enum, value_delimiter = ',')]
+        layers: Vec<Layer>,
+        /// Layers derived from the heights and the biomes to write, only as PNG
+        #[arg(long, value_enum, value_delimiter = ',')]
+        analysis: Vec<AnalysisLayer>,
+        /// Height between two contour lines, positive
+        #[arg(long, value_parser = parse_contour_interval, default_value_t = 0.1)]
+        contour_interval: f64,
+        #[arg(long, value_enum, default_value_t = LayerFormat::Png)]
+        format: LayerFormat,
+    },
+    /// City of the `city` section of the configuration, the region isn't used
+    City {
+        #[arg(long, value_enum, default_value_t = CityFormat::Png)]
+        format: CityFormat,
+        /// Surround the city with a wall this far from the buildings
+        #[arg(long)]
+        wall_padding: Option<i32>,
+        /// Also generate the inhabitants of the city
+        #[arg(long)]
+        population: bool,
+    },
+    /// Heights of the region with their metadata
+    Heightmap {
+        /// Formats to write, separated by commas
+        #[arg(long, value_enum, value_delimiter = ',', default_value = "png16")]
+        format: Vec<HeightmapFormat>,
+    },
+    /// Terrain mesh, Tiled map with the city of the configuration if any, or web map of the region
+    Export {
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+        /// Depth of the skirts around the mesh
+        #[arg(long)]
+        skirt: Option<f32>,
+    },
+    /// Print statistics of the region, nothing is written
+    Inspect {
+        /// JSON is printed on a single line, to gather the reports of several seeds
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+        /// Sample every this many cells in both directions
+        #[arg(long, default_value_t = 1)]
+        step: usize,
+    },
+    /// Print the seeds whose world satisfies constraints around the spawn, best first
+    ///
+    /// The constraints of the `search` section of the configuration are used for the options
+    /// not given, the seed and the region aren't used.
+    Search(SearchArgs),
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// Seeds to try, as `start..end`
+    #[arg(long, value_parser = parse_range::<u64>, default_value = "0..1000")]
+    seeds: (u64, u64),
+    /// Cell where the players start, as `x,y`
+    #[arg(long, value_parser = parse_spawn, allow_hyphen_values = true)]
+    spawn: Option<(isize, isize)>,
+    /// Biomes allowed at the spawn, separated by commas
+    #[arg(long, value_delimiter = ',')]
+    spawn_biome: Vec<BiomeType>,
+    /// Max distance from the spawn to the ocean, in cells
+    #[arg(long)]
+    ocean_within: Option<usize>,
+    /// Min number of different biomes within the radius
+    #[arg(long)]
+    min_biomes: Option<usize>,
+    /// Fraction of land within the radius, as `min..max`
+    #[arg(long, value_parser = parse_range::<f64>)]
+    land_fraction: Option<(f64, f64)>,
+    /// Radius around the spawn where the biomes and the land are counted
+    #[arg(long)]
+    radius: Option<usize>,
+    /// Sample every this many cells in both directions
+    #[arg(long)]
+    step: Option<usize>,
+    /// Number of seeds to print
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+    /// JSON is printed on a single line
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BiomeFormat {
+    /// Image, one pixel per cell
+    Png,
+    /// Regions of each biome as polygons
+    Geojson,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LayerFormat {
+    /// Image, one pixel per cell
+    Png,
+    /// Raw little-endian 32-bit floats, row by row with the northmost row first
+    R32,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CityFormat {
+    /// Raster image, one pixel per cell
+    Png,
+    /// Vector image with a legend
+    Svg,
+    /// Buildings, roads and wall as features
+    Geojson,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// Wavefront OBJ mesh
+    Obj,
+    /// Binary glTF mesh
+    Glb,
+    /// Tiled map with its tileset
+    Tiled,
+    /// Slippy map tiles of the biomes in `tiles/`, with a page to browse them
+    Tiles,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+/// Content of the `--config` file, a copy with the seed used is written next to the results
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// Seed of the world and of the city
+    seed: Option<u64>,
+    /// Scale of the world, the larger the larger the biomes
+    scale: f64,
+    /// City to generate, its seed is the one of the world
+    city: Option<CityConfig>,
+    /// Constraints of the `search` command
+    search: Option<SeedConstraints>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            scale: 1.,
+            city: None,
+            search: None,
+        }
+    }
+}
+
+/// Add the path to the error of an operation on a file
+fn with_path<T, E: Display>(path: &Path, result: std::result::Result<T, E>) -> Result<T> {
+    result.map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    let mut writer = BufWriter::new(with_path(path, File::create(path))?);
+    with_path(path, serde_json::to_writer(&mut writer, value))?;
+    // Flushing explicitly so that errors aren't lost when the writer is dropped
+    with_path(path, writer.flush())
+}
+
+/// Parse a range written as `start..end`
+fn parse_range<T: FromStr<Err: Display>>(s: &str) -> std::result::Result<(T, T), String> {
+    let Some((start, end)) = s.split_once("..") else {
+        return Err(format!("expected start..end, got {s}"));
+    };
+    let bound = |n: &str| n.trim().parse::<T>().map_err(|e| format!("{n}: {e}"));
+    Ok((bound(start)?, bound(end)?))
+}
+
+/// Parse a contour interval, rejecting the ones the contours can't be drawn with
+fn parse_contour_interval(s: &str) -> std::result::Result<f64, String> {
+    let interval = s.trim().parse::<f64>().map_err(|e| format!("{s}: {e}"))?;
+    AnalysisSettings::default()
+        .set_contour_interval(interval)
+        .validate()
+        .map_err(|e| e.to_string())?;
+    Ok(interval)
+}
+
+/// Parse a cell written as `x,y`
+fn parse_spawn(s: &str) -> std::result::Result<(isize, isize), String> {
+    let Some((x, y)) = s.split_once(',') else {
+        return Err(format!("expected x,y, got {s}"));
+    };
+    let coordinate = |n: &str| n.trim().parse::<isize>().map_err(|e| format!("{n}: {e}"));
+    Ok((coordinate(x)?, coordinate(y)?))
+}
+
+/// Configuration from the file, if any
+fn read_config(common: &Common) -> Result<Config> {
+    let config: Config = match &common.config {
+        Some(path) => with_path(
+            path,
+            serde_json::from_str(&with_path(path, fs::read_to_string(path))?),
+        )?,
+        None => Config::default(),
+    };
+    if config.scale <= 0. {
+        return Err(format!("the scale must be positive, got {}", config.scale).into());
+    }
+    Ok(config)
+}
+
+/// Seed of the command line or of the configuration, picking one if there is none
+///
+/// The seed is stored in the configuration and in its city.
+fn resolve_seed(common: &Common, config: &mut Config) -> u64 {
+    let seed = common.seed.or(config.seed).unwrap_or_else(|| {
+        let seed = thread_rng().next_u64();
+        // Not on the standard output, which may be parsed
+        eprintln!("seed: {seed}");
+        seed
+    });
+    config.seed = Some(seed);
+    if let Some(city) = config.city.take() {
+        config.city = Some(city.set_seed(seed));
+    }
+    seed
+}
+
+/// Print the seeds matching the constraints of the configuration and of the command line
+fn search(config: &Config, args: &SearchArgs) -> Result<()> {
+    let mut constraints = config.search.clone().unwrap_or_default();
+    if let Some(spawn) = args.spawn {
+        constraints = constraints.set_spawn(spawn);
+    }
+    if !args.spawn_biome.is_empty() {
+        constraints = constraints.set_spawn_biomes(args.spawn_biome.clone());
+    }
+    if args.ocean_within.is_some() {
+        constraints = constraints.set_ocean_within(args.ocean_within);
+    }
+    if let Some(min_biomes) = args.min_biomes {
+        constraints = constraints.set_min_biomes(min_biomes);
+    }
+    if let Some((min, max)) = args.land_fraction {
+        constraints = constraints.set_land_fraction(Some(min..=max));
+    }
+    if let Some(radius) = args.radius {
+        constraints = constraints.set_radius(radius);
+    }
+    if let Some(step) = args.step {
+        constraints = constraints.set_step(step);
+    }
+
+    let (start, end) = args.seeds;
+    let matches = search_seeds(start..end, config.scale, &constraints);
+    // Not on the standard output, which may be parsed
+    eprintln!(
+        "{} of {} seeds match",
+        matches.len(),
+        end.saturating_sub(start)
+    );
+    let matches = &matches[..matches.len().min(args.limit)];
+    match args.format {
+        ReportFormat::Text => print_matches(matches),
+        ReportFormat::Json => println!("{}", serde_json::to_string(matches)?),
+    }
+    Ok(())
+}
+
+fn print_matches(matches: &[SeedMatch]) {
+    println!(
+        "{:>4} {:>20} {:>6}  {:<24}{:>7}{:>7}{:>7}",
+        "rank", "seed", "score", "spawn biome", "ocean", "biomes", "land"
+    );
+    for (rank, m) in matches.iter().enumerate() {
+        let ocean = m
+            .ocean_distance
+            .map_or_else(|| "-".to_string(), |d| format!("{d:.0}"));
+        println!(
+            "{:>4} {:>20} {:>6.3}  {:<24}{ocean:>7}{:>7}{:>6.1}%",
+            rank + 1,
+            m.seed,
+            m.score,
+            format!("{:?}", m.spawn_biome),
+            m.biomes,
+            m.land_fraction * 100.
+        );
+    }
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    let Common {
+        out_dir, region, ..
+    } = &cli.common;
+    let region = *region;
+    let mut config = read_config(&cli.common)?;
+    if let Command::Search(args) = &cli.command {
+        return search(&config, args);
+    }
+    let seed = resolve_seed(&cli.common, &mut config);
+    let world_gen = WorldGen::new(config.scale, Some(seed));
+    let path = |name: &str| out_dir.join(name);
+    if let Command::Inspect { format, step } = cli.command {
+        let report = WorldReport::generate(&world_gen, region, step);
+        match format {
+            ReportFormat::Text => print!("scale {}, {report}", config.scale),
+            ReportFormat::Json => {
+                let mut json = serde_json::to_value(&report)?;
+                json["scale"] = config.scale.into();
+                println!("{json}");
+            }
+        }
+        return Ok(());
+    }
+    with_path(out_dir, fs::create_dir_all(out_dir))?;
+    // Keep the configuration next to the results to generate them again
+    let config_path = path("worldgen.json");
+    with_path(
+        &config_path,
+        fs::write(&config_path, serde_json::to_string_pretty(&config)?),
+    )?;
+
+    match &cli.command {
+        Command::Biomes { format } => match format {
+            BiomeFormat::Png => {
+                let path = path("biomes.png");
+                let file = BufWriter::new(with_path(&path, File::create(&path))?);
+                let color = |_, cell: &Cell| Rgb(cell.biome.color());
+                with_path(
+                    &path,
+                    stream_region_png(&world_gen, region, 1, vec![(file, &color)], |_| {}),
+                )?;
+            }
+            BiomeFormat::Geojson => write_json(
+                &path("biomes.geojson"),
+                &biome_geojson(&world_gen, region.origin(), region.size()),
+            )?,
+        },
+        Command::Layers {
+            layers,
+            analysis,
+            contour_interval,
+            format,
+        } => {
+            if !analysis.is_empty() {
+                let settings = AnalysisSettings::default().set_contour_interval(*contour_interval);
+                let analysis_maps = Analysis::generate(&world_gen, region, settings);
+                for layer in analysis {
+                    let path = path(&format!("{}.png", layer.name()));
+                    with_path(&path, analysis_maps.image(*layer).save(&path))?;
+                }
+            }
+            let layers = match (layers.is_empty(), analysis.is_empty()) {
+                (true, true) => &Layer::ALL[..],
+                (true, false) => return Ok(()),
+                _ => layers,
+            };
+            let cells = generate_region(&world_gen, region);
+            for layer in layers {
+                match format {
+                    LayerFormat::Png => {
+                        let path = path(&format!("{}.png", layer.name()));
+                        with_path(&path, layer_image(&cells, region, *layer).save(&path))?;
+                    }
+                    LayerFormat::R32 => {
+                        #[allow(clippy::cast_possible_truncation)]
+                        let bytes = cells
+                            .iter()
+                            .flat_map(|cell| (layer.value(cell) as f32).to_le_bytes())
+                            .collect::<Vec<_>>();
+                        let path = path(&format!("{}.r32", layer.name()));
+                        with_path(&path, fs::write(&path, bytes))?;
+                    }
+                }
+            }
+        }
+        Command::City {
+            format,
+            wall_padding,
+            population,
+        } => {
+            let city_config = config
+                .city
+                .clone()
+                .unwrap_or_else(|| CityConfig::default().set_seed(seed));
+            let city_gen = generate_city(city_config, *wall_padding)?;
+            match format {
+                CityFormat::Png => {
+                    let path = path("city.png");
+                    with_path(&path, city_image(&city_gen).save(&path))?;
+                }
+                CityFormat::Svg => {
+                    let path = path("city.svg");
+                    with_path(&path, fs::write(&path, city_svg(&city_gen)))?;
+                }
+                CityFormat::Geojson => write_json(&path("city.geojson"), &city_geojson(&city_gen))?,
+            }
+            if *population {
+                let population = PopulationGenerator::new(seed).generate(&city_gen);
+                write_json(&path("population.json"), &population)?;
+            }
+        }
+        Command::Heightmap { format: formats } => {
+            let heightmap = Heightmap::generate(&world_gen, region.origin(), region.size());
+            for format in formats {
+                let path = path(&format!("heightmap.{}", format.extension()));
+                with_path(&path, heightmap.save(&path, *format))?;
+            }
+            write_json(&path("heightmap.json"), &heightmap.metadata(formats))?;
+        }
+        Command::Export { format, skirt } => match format {
+            ExportFormat::Obj | ExportFormat::Glb => {
+                let format = if matches!(format, ExportFormat::Obj) {
+                    MeshFormat::Obj
+                } else {
+                    MeshFormat::Glb
+                };
+                let settings = MeshSettings::default()
+                    .set_origin([i32::try_from(region.x)?, i32::try_from(region.y)?])
+                    .set_size([u32::try_from(region.width)?, u32::try_from(region.height)?])
+                    .set_skirt_depth(*skirt);
+                let path = path(&format!("terrain.{}", format.extension()));
+                let mut file = BufWriter::new(with_path(&path, File::create(&path))?);
+                with_path(
+                    &path,
+                    TerrainMesh::generate(&world_gen, &settings).write(&mut file, format),
+                )?;
+            }
+            ExportFormat::Tiled => {
+                let city_gen = match &config.city {
+                    Some(city_config) => Some(generate_city(city_config.clone(), None)?),
+                    None => None,
+                };
+                let tileset = path("biome_tileset.png");
+                with_path(&tileset, biome_tileset_image().save(&tileset))?;
+                let map = tiled_map(
+                    &world_gen,
+                    config.scale,
+                    region.origin(),
+                    region.size(),
+                    city_gen.as_ref(),
+                    "biome_tileset.png",
+                );
+                write_json(&path("map.tmj"), &map)?;
+            }
+            ExportFormat::Tiles => {
+                let dir = path("tiles");
+                let pyramid = TilePyramid::new(region);
+                let count = with_path(&dir, pyramid.render(&world_gen, &dir))?;
+                let page = dir.join("index.html");
+                let title = format!("World {seed}");
+                with_path(&page, fs::write(&page, pyramid.viewer_html(&title)))?;
+                println!(
+                    "{count} tiles, zoom 0 to {}, serve {} to browse them",
+                    pyramid.max_zoom,
+                    dir.display()
+                );
+            }
+        },
+        Command::Inspect { .. } | Command::Search(_) => {
+            unreachable!("handled before writing anything")
+        }
+    }
+    Ok(())
+}
+
+fn generate_city(config: CityConfig, wall_padding: Option<i32>) -> Result<CityGenerator> {
+    let mut city_gen = CityGenerator::new(config);
+    city_gen.generate()?;
+    if let Some(padding) = wall_padding {
+        city_gen.generate_wall(padding);
+    }
+    Ok(city_gen)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
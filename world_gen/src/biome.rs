@@ -1,3 +1,6 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
 #[derive(Copy, Clone)]
 pub enum TemperatureLevel {
     Freezing,
@@ -99,7 +102,7 @@ impl From<BiomeSettings> for IntermediateBiomeType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum BiomeType {
     // Water biomes
     ArcticWater,
@@ -130,6 +133,57 @@ pub enum BiomeType {
     IceMountains,
 }
 impl BiomeType {
+    /// Every biome, in declaration order
+    pub const ALL: [Self; 20] = [
+        Self::ArcticWater,
+        Self::DeepTemperateWater,
+        Self::DeepTropicalWater,
+        Self::ShallowTemperateWater,
+        Self::ShallowTropicalWater,
+        Self::ShallowIceWater,
+        Self::TropicalBeach,
+        Self::TemperateBeach,
+        Self::IceField,
+        Self::Plain,
+        Self::Savanna,
+        Self::Forest,
+        Self::TropicalRainforest,
+        Self::Desert,
+        Self::Taiga,
+        Self::Lake,
+        Self::Hills,
+        Self::Dunes,
+        Self::Mountains,
+        Self::IceMountains,
+    ];
+
+    /// If the biome is covered by water, the sea or a lake
+    #[must_use]
+    pub const fn is_water(self) -> bool {
+        matches!(
+            self,
+            Self::ArcticWater
+                | Self::DeepTemperateWater
+                | Self::DeepTropicalWater
+                | Self::ShallowTemperateWater
+                | Self::ShallowTropicalWater
+                | Self::ShallowIceWater
+                | Self::Lake
+        )
+    }
+
+    /// If the biome is the sea, water that isn't a lake
+    #[must_use]
+    pub const fn is_ocean(self) -> bool {
+        self.is_water() && !matches!(self, Self::Lake)
+    }
+
+    /// If the biome can be crossed on foot, land that isn't a mountain
+    #[must_use]
+    pub const fn is_walkable(self) -> bool {
+        !self.is_water() && !matches!(self, Self::Mountains | Self::IceMountains)
+    }
+
     pub fn color(&self) -> [u8; 3] {
         match self {
             BiomeType::ArcticWater => [204, 229, 255],
@@ -160,6 +214,21 @@ impl BiomeType {
         }
     }
 }
+
+impl FromStr for BiomeType {
+    type Err = String;
+
+    /// Parse the name of a biome ignoring the case, `_` and `-`, like `deep-temperate-water`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let simplify = |name: &str| name.replace(['_', '-'], "").to_lowercase();
+        let name = simplify(s);
+        Self::ALL
+            .into_iter()
+            .find(|biome| simplify(&format!("{biome:?}")) == name)
+            .ok_or_else(|| format!("unknown biome {s}"))
+    }
+}
+
 impl From<IntermediateBiome> for BiomeType {
     fn from(value: IntermediateBiome) -> Self {
         match value.biome_type {
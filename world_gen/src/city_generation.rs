@@ -1,86 +1,226 @@
+use clap::ValueEnum;
 use pathfinding::prelude::astar;
-use rand::{seq::IteratorRandom, Rng, SeedableRng};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
-use rayon::slice::ParallelSliceMut;
-use std::{collections::HashMap, ops::Range};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt};
+
+pub use blocks::Block;
+pub use config::CityConfig;
+use config::IMPORTANT_BUILDING_MARGIN;
+pub(crate) use footprint::{line_cells, neighbours};
+pub use footprint::{Corner, Footprint, Side};
+use grid::{BuildingIndex, OccupancyGrid};
+pub use streets::LayoutMode;
+pub use wall::CityWall;
+
+mod blocks;
+mod config;
+mod footprint;
+mod grid;
+mod growth;
+mod streets;
+mod wall;
 
 const CITY_BOUNDS_OFFSET: i32 = 20;
+/// Buildings bigger than this get a second door half of the time
+const LARGE_BUILDING_AREA: i32 = 300;
+/// How far to look for a road to align a building with
+const ROAD_SEARCH_RADIUS: i32 = 30;
+/// Failed attempts in a row to place a building before giving up
+const MAX_FAILED_PLACEMENTS: usize = 10_000;
+/// Margin around the buildings linked by a road in which the road is searched first
+const ROAD_SEARCH_MARGIN: i32 = 64;
+/// Cost of going through an existing road
+const ROAD_COST: i32 = 10;
+/// Cost of building a road on a free cell
+const NEW_ROAD_COST: i32 = 5 * ROAD_COST;
+/// Types given to important buildings, in order of creation
+const IMPORTANT_BUILDING_TYPES: [BuildingType; 5] = [
+    BuildingType::TownHall,
+    BuildingType::Market,
+    BuildingType::Temple,
+    BuildingType::Barracks,
+    BuildingType::Guildhall,
+];
+
+/// Reasons why a city can't be generated
+#[derive(Clone, Debug, PartialEq)]
+pub enum CityGenError {
+    /// A range of the generator is empty or contains values lower than 1
+    InvalidRange(&'static str),
+    /// The minimum distance between buildings is lower than their minimum size
+    DistanceTooSmall { distance: i32, size: i32 },
+    /// The grid bias is not between 0 and 1
+    InvalidGridBias(f64),
+    /// The scale of the important buildings is lower than 1
+    InvalidScale(i32),
+    /// The important buildings can't be spread over such a small distance
+    MaxDistanceTooSmall {
+        max_distance: i32,
+        scale: i32,
+        important_buildings: usize,
+    },
+    /// Normal buildings are built around important ones, there must be at least one
+    NoImportantBuildings,
+    /// The city has no building
+    NoBuildings,
+    /// No room was found for a building after too many attempts
+    PlacementFailed { placed: usize, requested: usize },
+}
 
+impl fmt::Display for CityGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRange(name) => write!(f, "{name} is empty or not positive"),
+            Self::DistanceTooSmall { distance, size } => write!(
+                f,
+                "min distance {distance} is lower than the min building size {size}"
+            ),
+            Self::InvalidGridBias(grid_bias) => {
+                write!(f, "grid bias {grid_bias} is not between 0 and 1")
+            }
+            Self::InvalidScale(scale) => write!(f, "invalid important building scale {scale}"),
+            Self::MaxDistanceTooSmall {
+                max_distance,
+                scale,
+                important_buildings,
+            } => write!(
+                f,
+                "max distance {max_distance} is too small for {important_buildings} important \
+                 buildings scaled by {scale}"
+            ),
+            Self::NoImportantBuildings => write!(f, "at least one important building is needed"),
+            Self::NoBuildings => write!(f, "the city has no building"),
+            Self::PlacementFailed { placed, requested } => write!(
+                f,
+                "only {placed} out of {requested} buildings could be placed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CityGenError {}
+
+#[derive(Clone, Copy, Debug)]
 enum CellType {
     Road,
     Building,
+    Wall,
+}
+
+/// What a building is used for
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, ValueEnum)]
+pub enum BuildingType {
+    // Normal buildings
+    House,
+    Farm,
+    Shop,
+    Workshop,
+    Tavern,
+    Warehouse,
+    // Important buildings
+    TownHall,
+    Market,
+    Temple,
+    Barracks,
+    Guildhall,
+}
+impl BuildingType {
+    /// Every building type, in declaration order
+    pub const ALL: [Self; 11] = [
+        Self::House,
+        Self::Farm,
+        Self::Shop,
+        Self::Workshop,
+        Self::Tavern,
+        Self::Warehouse,
+        Self::TownHall,
+        Self::Market,
+        Self::Temple,
+        Self::Barracks,
+        Self::Guildhall,
+    ];
 }
 
 /// Building of the city
 #[derive(Clone, Debug, PartialEq, PartialOrd, Ord, Eq)]
 pub struct Building {
-    /// Coordinates of the door
-    pub door: (i32, i32),
-    /// x coordinate of top left corner
+    /// Coordinates of the doors, the first one is the main entrance
+    pub doors: Vec<(i32, i32)>,
+    /// x coordinate of top left corner of the bounding box
     pub x: i32,
-    /// y coordinate of top left corner
+    /// y coordinate of top left corner of the bounding box
     pub y: i32,
-    /// Width of the building
+    /// Width of the bounding box
     pub width: i32,
-    /// Height of the building
+    /// Height of the bounding box
     pub height: i32,
+    /// Ground shape of the building
+    pub footprint: Footprint,
     /// If the building is important
     pub is_important: bool,
+    /// What the building is used for
+    pub building_type: BuildingType,
     /// Unique identifier
     pub id: usize,
 }
 impl Building {
-    /// Check if two buildings overlap
-    fn overlaps(&self, other: &Building, offset: i32) -> bool {
-        self.x - offset < other.x + other.width
-            && self.x + self.width + offset > other.x
-            && self.y - offset < other.y + other.height
-            && self.y + self.height + offset > other.y
+    /// Coordinates of the main entrance
+    #[must_use]
+    pub fn door(&self) -> (i32, i32) {
+        self.doors[0]
     }
     /// Check if a point is inside the building (including its walls)
     fn contains(&self, pos: (i32, i32)) -> bool {
-        let (x, y) = pos;
-        x >= self.x && x <= self.x + self.width && y >= self.y && y <= self.y + self.height
+        self.footprint.contains(pos)
     }
 
-    /// Create a building from a rectangle and ID, randomizes the door
-    fn with_random_door(
-        rng: &mut ChaCha8Rng,
-        x: i32,
-        y: i32,
-        width: i32,
-        height: i32,
-        id: usize,
-    ) -> Self {
-        let (door_x, door_y) = if rng.gen_bool(0.5) {
-            // on northern or southern side
-            if rng.gen_bool(0.5) {
-                // northern side
-                (rng.gen_range(x..x + width), y)
-            } else {
-                // southern side
-                (rng.gen_range(x..x + width), y + height)
-            }
+    /// Create a building from a footprint and ID, randomizes the doors
+    fn with_random_doors(rng: &mut ChaCha8Rng, footprint: Footprint, id: usize) -> Self {
+        let mut candidates = footprint.door_candidates();
+        if candidates.is_empty() {
+            // Tiny buildings only have corners
+            candidates = footprint.boundary_cells();
+        }
+        let door_count = if footprint.area() > LARGE_BUILDING_AREA && rng.gen_bool(0.5) {
+            2
         } else {
-            // on eastern or western side
-            if rng.gen_bool(0.5) {
-                // eastern side
-                (x + width, rng.gen_range(y..y + height))
-            } else {
-                // western side
-                (x, rng.gen_range(y..y + height))
-            }
+            1
         };
+        let doors = candidates
+            .choose_multiple(rng, door_count)
+            .copied()
+            .collect();
+        Self::with_doors(footprint, doors, id)
+    }
+    /// Create a building from a footprint, its doors and ID
+    fn with_doors(footprint: Footprint, doors: Vec<(i32, i32)>, id: usize) -> Self {
+        let (min_x, min_y, max_x, max_y) = footprint.bounding_box();
         Self {
             is_important: false,
-            door: (door_x, door_y),
-            x,
-            y,
-            width,
-            height,
+            building_type: BuildingType::House,
+            doors,
+            x: min_x,
+            y: min_y,
+            width: max_x - min_x,
+            height: max_y - min_y,
+            footprint,
             id,
         }
     }
+    /// Multiply every coordinate of the building by `factor`
+    fn scale(&mut self, factor: i32) {
+        self.x *= factor;
+        self.y *= factor;
+        self.width *= factor;
+        self.height *= factor;
+        self.footprint = self.footprint.scaled(factor);
+        for door in &mut self.doors {
+            door.0 *= factor;
+            door.1 *= factor;
+        }
+    }
     /// Make the building important
     fn make_important(self) -> Self {
         Self {
@@ -91,14 +231,21 @@ impl Building {
 }
 
 /// Random city generator
+#[derive(Clone)]
 pub struct CityGenerator {
     rng: ChaCha8Rng,
-    /// Buildings of the city
-    pub buildings: HashMap<(i32, i32), Building>,
+    /// Buildings of the city, ordered by position so that iterating them is deterministic
+    pub buildings: BTreeMap<(i32, i32), Building>,
     /// Buildings of the city
     pub important_buildings: Vec<(i32, i32)>,
-    /// Roads of the city
+    /// Roads of the city, the main ones linking important buildings come first
     pub roads: Vec<Vec<(i32, i32)>>,
+    /// Number of main roads
+    pub main_roads: usize,
+    /// Wall surrounding the city, if any
+    pub wall: Option<CityWall>,
+    /// Number of years the city grew for
+    pub age: u32,
     /// x coordinate of the leftmost building
     pub min_x: i32,
     /// y coordinate of the topmost building
@@ -108,76 +255,131 @@ pub struct CityGenerator {
     /// y coordinate of the bottommost building
     pub max_y: i32,
     /// Lets us know if a point is not free
-    is_something: HashMap<(i32, i32), CellType>,
-    /// Min and max width of the buildings
-    width_bound: Range<i32>,
-    /// Min and max height of the buildings
-    height_bound: Range<i32>,
-    /// Min and max distance between buildings
-    distance_bound: Range<i32>,
-    /// Max distance between important buildings
-    important_buildings_max_distance: i32,
+    is_something: OccupancyGrid,
+    /// Spatial index of the buildings
+    index: BuildingIndex,
+    /// Parameters of the generation
+    config: CityConfig,
 }
 
 impl CityGenerator {
     #[must_use]
-    pub fn new(
-        seed: u64,
-        width_bound: Range<i32>,
-        height_bound: Range<i32>,
-        distance_bound: Range<i32>,
-        important_buildings_max_distance: i32,
-    ) -> Self {
+    pub fn new(config: CityConfig) -> Self {
         Self {
-            rng: ChaCha8Rng::seed_from_u64(seed),
+            rng: ChaCha8Rng::seed_from_u64(config.seed),
             min_x: i32::MAX,
             min_y: i32::MAX,
             max_x: 0,
             max_y: 0,
-            buildings: HashMap::new(),
+            buildings: BTreeMap::new(),
             important_buildings: vec![],
-            is_something: HashMap::new(),
+            is_something: OccupancyGrid::default(),
+            index: BuildingIndex::default(),
             roads: vec![],
-            width_bound,
-            height_bound,
-            distance_bound,
-            important_buildings_max_distance,
+            main_roads: 0,
+            wall: None,
+            age: 0,
+            config,
         }
     }
-    pub fn generate(
+    /// Parameters of the generation
+    #[must_use]
+    pub const fn config(&self) -> &CityConfig {
+        &self.config
+    }
+    /// Generate the city
+    ///
+    /// # Errors
+    ///
+    /// Fails if the configuration is invalid or if there is no room left for the buildings.
+    pub fn generate(&mut self) -> Result<(), CityGenError> {
+        self.config.validate()?;
+        let CityConfig {
+            buildings: normal_buildings,
+            important_buildings,
+            important_building_scale,
+            ..
+        } = self.config;
+        match self.config.layout {
+            LayoutMode::BuildingFirst => {
+                self.generate_important_buildings(important_buildings, important_building_scale)?;
+                let placed = if self.config.subdivide_blocks {
+                    self.generate_block_buildings(normal_buildings, 0)
+                } else {
+                    0
+                };
+                self.generate_buildings(normal_buildings - placed)?;
+                self.update_borders();
+            }
+            LayoutMode::StreetFirst => {
+                let requested = normal_buildings + important_buildings;
+                let placed = self.generate_street_first(normal_buildings, important_buildings);
+                if placed < requested {
+                    return Err(CityGenError::PlacementFailed { placed, requested });
+                }
+            }
+        }
+        if self.buildings.is_empty() {
+            return Err(CityGenError::NoBuildings);
+        }
+        self.assign_building_types();
+        Ok(())
+    }
+    /// Give every building a type, important buildings get civic types
+    fn assign_building_types(&mut self) {
+        let mut ids = self
+            .buildings
+            .iter()
+            .map(|(pos, b)| (b.id, *pos))
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        let mut important_count = 0;
+        for (_, pos) in ids {
+            let building = self.buildings.get_mut(&pos).unwrap();
+            building.building_type = if building.is_important {
+                important_count += 1;
+                IMPORTANT_BUILDING_TYPES[(important_count - 1) % IMPORTANT_BUILDING_TYPES.len()]
+            } else {
+                Self::random_building_type(&mut self.rng)
+            };
+        }
+    }
+    /// Pick the type of a normal building
+    fn random_building_type(rng: &mut ChaCha8Rng) -> BuildingType {
+        match rng.gen_range(0..100) {
+            0..70 => BuildingType::House,
+            70..76 => BuildingType::Shop,
+            76..84 => BuildingType::Workshop,
+            84..88 => BuildingType::Tavern,
+            88..92 => BuildingType::Warehouse,
+            _ => BuildingType::Farm,
+        }
+    }
+    fn generate_important_buildings(
         &mut self,
-        normal_buildings: usize,
-        important_buildings: usize,
+        n: usize,
         important_building_scale: i32,
-    ) {
-        println!("Generating important buildings");
-        self.generate_important_buildings(important_buildings, important_building_scale);
-        println!("Generating normal buildings");
-        self.generate_buildings(normal_buildings);
-        self.update_borders();
-    }
-    fn generate_important_buildings(&mut self, n: usize, important_building_scale: i32) {
+    ) -> Result<(), CityGenError> {
         // generate the important buildings with a smaller scale
 
         for _ in 0..n {
             // New building
-            let b1 = self.generate_random_important_building(important_building_scale);
+            let b1 = self
+                .generate_random_important_building(important_building_scale)
+                .ok_or(CityGenError::PlacementFailed {
+                    placed: self.buildings.len(),
+                    requested: n,
+                })?;
             // Register the building in the map
-            for x in b1.x..=b1.x + b1.width {
-                for y in b1.y..=b1.y + b1.height {
-                    self.is_something.insert((x, y), CellType::Building);
-                }
-            }
-            // Keep door free to go through
-            self.is_something.remove(&b1.door);
+            self.register_building(&b1);
 
             // Keep track of the important building
             self.important_buildings.push((b1.x, b1.y));
             self.update_borders_from_new_building(&b1);
-            self.buildings.insert((b1.x, b1.y), b1);
+            self.insert_building(b1);
         }
-        let mut buildings = self.buildings.values().collect::<Vec<&Building>>(); // We'll iterate over the buildings
-        buildings.par_sort_by(|b1, b2| b1.x.cmp(&b2.x).then(b1.y.cmp(&b2.y)));
+        let buildings = self.buildings.values().collect::<Vec<&Building>>(); // We'll iterate over the buildings
 
         for &b1 in &buildings {
             for &b2 in &buildings {
@@ -205,106 +407,94 @@ impl CityGenerator {
             self.max_y *= important_building_scale;
 
             for building in self.buildings.values_mut() {
-                building.x *= important_building_scale;
-                building.y *= important_building_scale;
-                building.width *= important_building_scale;
-                building.height *= important_building_scale;
-
-                building.door.0 *= important_building_scale;
-                building.door.1 *= important_building_scale;
+                building.scale(important_building_scale);
 
-                for x in building.x..=building.x + building.width {
-                    for y in building.y..=building.y + building.height {
-                        self.is_something.insert((x, y), CellType::Building);
-                    }
+                for pos in building.footprint.cells() {
+                    self.is_something.insert(pos, CellType::Building);
+                }
+                for door in &building.doors {
+                    self.is_something.remove(door);
                 }
-                self.is_something.remove(&building.door);
             }
             for road in &mut self.roads {
-                let mut scaled_road = vec![];
-                for i in 0..road.len() - 1 {
-                    let mut direction = (road[i + 1].0 - road[i].0, road[i + 1].1 - road[i].1);
-                    direction = (
-                        if direction.0 == 0 {
-                            0
-                        } else {
-                            direction.0 / direction.0.abs()
-                        },
-                        if direction.1 == 0 {
-                            0
-                        } else {
-                            direction.1 / direction.1.abs()
-                        },
-                    );
-
-                    let mut position = (
-                        road[i].0 * important_building_scale,
-                        road[i].1 * important_building_scale,
-                    );
-                    for _i in 0..important_building_scale {
-                        scaled_road.push(position);
-                        self.is_something.insert(position, CellType::Road);
-                        position = (position.0 + direction.0, position.1 + direction.1);
-                    }
+                *road = scale_road(road, important_building_scale);
+                for position in road.iter() {
+                    self.is_something.insert(*position, CellType::Road);
                 }
-                *road = scaled_road;
+            }
+            // Keys are kept but the bounding boxes changed
+            let keys = self.index.keys().to_vec();
+            self.index.clear();
+            for key in keys {
+                let bounding_box = self.buildings[&key].footprint.bounding_box();
+                self.index.insert(key, bounding_box);
             }
         }
+        self.main_roads = self.roads.len();
+        Ok(())
+    }
+    /// Generate a random important building, if there is room for it
+    fn generate_random_important_building(&mut self, scale_factor: i32) -> Option<Building> {
+        (0..MAX_FAILED_PLACEMENTS).find_map(|_| {
+            let building = self.random_important_building(scale_factor);
+            self.overlapping_buildings(&building.footprint, IMPORTANT_BUILDING_MARGIN)
+                .is_empty()
+                .then_some(building)
+        })
     }
-    /// Generate a random important building
-    fn generate_random_important_building(&mut self, scale_factor: i32) -> Building {
+    /// Random important building, it may overlap with existing buildings
+    fn random_important_building(&mut self, scale_factor: i32) -> Building {
         let (x, y) = (
             self.rng.gen_range(
-                -(self.important_buildings_max_distance / (scale_factor * 2))
-                    ..(self.important_buildings_max_distance / (scale_factor * 2)),
+                -(self.config.important_buildings_max_distance / (scale_factor * 2))
+                    ..(self.config.important_buildings_max_distance / (scale_factor * 2)),
             ),
             self.rng.gen_range(
-                (-self.important_buildings_max_distance / (scale_factor * 2))
-                    ..(self.important_buildings_max_distance / (scale_factor * 2)),
+                (-self.config.important_buildings_max_distance / (scale_factor * 2))
+                    ..(self.config.important_buildings_max_distance / (scale_factor * 2)),
             ),
         );
-        let width = (self.rng.gen_range(self.width_bound.clone()) + scale_factor) / scale_factor;
-        let height = (self.rng.gen_range(self.height_bound.clone()) + scale_factor) / scale_factor;
+        let width =
+            (self.rng.gen_range(self.config.width_bound.clone()) + scale_factor) / scale_factor;
+        let height =
+            (self.rng.gen_range(self.config.height_bound.clone()) + scale_factor) / scale_factor;
 
-        let building =
-            Building::with_random_door(&mut self.rng, x, y, width, height, 0).make_important();
-        if self.buildings.values().any(|b| b.overlaps(&building, 3)) {
-            self.generate_random_important_building(scale_factor)
-        } else {
-            building
-        }
+        let id = self.buildings.len();
+        Building::with_random_doors(&mut self.rng, Footprint::rectangle(x, y, width, height), id)
+            .make_important()
     }
-    fn generate_buildings(&mut self, mut n: usize) {
+    fn generate_buildings(&mut self, mut n: usize) -> Result<(), CityGenError> {
         let init_n = n as f32;
+        let mut failed_placements = 0;
         while n > 0 {
             let Building {
-                door: _,
-                is_important: _,
                 x,
                 y,
                 width,
                 height,
-                id: _,
-            } = {
-                let values = self.buildings.values();
-                let mut a = values.into_iter().collect::<Vec<&Building>>();
-                a.par_sort_by(|b1, b2| b1.x.cmp(&b2.x).then(b1.y.cmp(&b2.y)));
-                a.into_iter().choose(&mut self.rng).unwrap()
-            };
+                ..
+            } = self
+                .index
+                .keys()
+                .choose(&mut self.rng)
+                .and_then(|key| self.buildings.get(key))
+                .ok_or(CityGenError::NoImportantBuildings)?;
             let x_center = x + width / 2;
             let y_center = y + height / 2;
 
-            // let distance_x = self.rng.gen_range(self.distance_bound.clone());
-            // let distance_y = self.rng.gen_range(self.distance_bound.clone());
+            // let distance_x = self.rng.gen_range(self.config.distance_bound.clone());
+            // let distance_y = self.rng.gen_range(self.config.distance_bound.clone());
 
-            let distance_x = ((self.distance_bound.end - self.distance_bound.start) as f32
+            let distance_x = ((self.config.distance_bound.end - self.config.distance_bound.start)
+                as f32
                 * n as f32
                 / init_n) as i32
-                + self.distance_bound.start;
+                + self.config.distance_bound.start;
 
-            let distance_y = ((self.distance_bound.end - self.distance_bound.start) as f32
+            let distance_y = ((self.config.distance_bound.end - self.config.distance_bound.start)
+                as f32
                 * (n as f32 / init_n)) as i32
-                + self.distance_bound.start;
+                + self.config.distance_bound.start;
 
             let spawn_x = if self.rng.gen_bool(0.5) {
                 x_center + distance_x
@@ -318,94 +508,213 @@ impl CityGenerator {
                 y_center - distance_y
             };
 
-            let width = self.rng.gen_range(self.width_bound.clone());
-            let height = self.rng.gen_range(self.height_bound.clone());
+            let width = self.rng.gen_range(self.config.width_bound.clone());
+            let height = self.rng.gen_range(self.config.height_bound.clone());
 
-            let offset = 8; // minimum distance between buildings
-            let new_building =
-                Building::with_random_door(&mut self.rng, spawn_x, spawn_y, width, height, n);
-            let overlaps =
-                        // seems inefficient but it's A* that's the bottleneck
-                            self
-                            .buildings
+            if self
+                .try_place_building((spawn_x, spawn_y), width, height)
+                .is_some()
+            {
+                n -= 1;
+                failed_placements = 0;
+            } else {
+                failed_placements += 1;
+                if failed_placements == MAX_FAILED_PLACEMENTS {
+                    return Err(CityGenError::PlacementFailed {
+                        placed: init_n as usize - n,
+                        requested: init_n as usize,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Place a new building if there is room for it and link it to the closest important building
+    ///
+    /// Returns the ID of the new building
+    fn try_place_building(
+        &mut self,
+        (spawn_x, spawn_y): (i32, i32),
+        width: i32,
+        height: i32,
+    ) -> Option<usize> {
+        let offset = 8; // minimum distance between buildings
+        let id = self.buildings.len();
+        let footprint = self.random_footprint(spawn_x, spawn_y, width, height);
+        let overlaps = !self.overlapping_buildings(&footprint, offset).is_empty()
+                        // it's okay to only check on building walls and not inside
+                        || footprint
+                            .boundary_cells()
                             .iter()
-                            .any(|(_, b)| b.overlaps(&new_building, offset) && b != &new_building)
-                            // it's okay to only check on building walls and not inside
+                            .any(|pos| self.is_something.contains_key(pos));
+        if overlaps {
+            return None;
+        }
+        // Once walled, the city only grows inside of the wall, which is convex
+        if let Some(wall) = &self.wall {
+            let area = Footprint {
+                vertices: wall.outline.clone(),
+            };
+            if !footprint.vertices.iter().all(|pos| area.contains(*pos)) {
+                return None;
+            }
+        }
+        let new_building = Building::with_random_doors(&mut self.rng, footprint, id);
 
-                            || (spawn_x..=spawn_x + width)
-                                .any(|x| self.is_something.contains_key(&(x, spawn_y)))
+        let closest_important_building = self
+            .important_buildings
+            .iter()
+            .min_by_key(|(x, y)| ((x - spawn_x).abs() + (y - spawn_y).abs(), *x, *y))
+            .and_then(|pos| self.buildings.get(pos))?
+            .clone();
 
-                            || (spawn_x..=spawn_x + width
-            )                    .any(|x| self.is_something.contains_key(&(x, spawn_y + height)))
+        self.register_building(&new_building);
+        self.update_borders_from_new_building(&new_building);
+        let road = if let Some((road, _)) =
+            self.generate_road(&new_building, &closest_important_building)
+        {
+            road
+        } else {
+            // The building stays unconnected
+            vec![]
+        };
+        for (x, y) in &road {
+            self.is_something.insert((*x, *y), CellType::Road);
+        }
+        self.insert_building(new_building);
+        self.roads.push(road);
+        Some(id)
+    }
 
-                            || (spawn_y..=spawn_y + height)
-                                .any(|y| self.is_something.contains_key(&(spawn_x, y)))
+    /// Add a building to the city and to the spatial index, the cells are not marked as occupied
+    pub(super) fn insert_building(&mut self, building: Building) {
+        self.index
+            .insert((building.x, building.y), building.footprint.bounding_box());
+        self.buildings.insert((building.x, building.y), building);
+    }
 
-                            ||( spawn_y..=spawn_y + height
-            )                    .any(|y| self.is_something.contains_key(&(spawn_x + width, y)))
+    /// Buildings closer than `offset` to a footprint
+    fn overlapping_buildings(&self, footprint: &Footprint, offset: i32) -> Vec<&Building> {
+        let (min_x, min_y, max_x, max_y) = footprint.bounding_box();
+        self.index
+            .query((
+                min_x - offset,
+                min_y - offset,
+                max_x + offset,
+                max_y + offset,
+            ))
+            .iter()
+            .filter_map(|key| self.buildings.get(key))
+            .filter(|b| b.footprint.overlaps(footprint, offset))
+            .collect()
+    }
 
-                            ;
+    /// Mark the cells of a building as occupied, except for its doors
+    fn register_building(&mut self, building: &Building) {
+        for pos in building.footprint.cells() {
+            self.is_something.insert(pos, CellType::Building);
+        }
+        // Keep doors free to go through
+        for door in &building.doors {
+            self.is_something.remove(door);
+        }
+    }
 
-            if !overlaps {
-                let buildings_clone = self.buildings.clone();
-                let closest_important_building = buildings_clone
-                    .get(
-                        self.important_buildings
-                            .iter()
-                            .min_by_key(|(x, y)| (x - spawn_x).abs() + (y - spawn_y).abs())
-                            .unwrap(),
-                    )
+    /// Pick a random footprint fitting in the given rectangle, rotated ones follow the nearest road
+    fn random_footprint(&mut self, x: i32, y: i32, width: i32, height: i32) -> Footprint {
+        let wide_enough = width >= 6 && height >= 6;
+        match self.rng.gen_range(0..100) {
+            55..70 if wide_enough => {
+                let cut = (
+                    self.rng.gen_range(width / 3..=width / 2),
+                    self.rng.gen_range(height / 3..=height / 2),
+                );
+                let corner = *[
+                    Corner::NorthWest,
+                    Corner::NorthEast,
+                    Corner::SouthEast,
+                    Corner::SouthWest,
+                ]
+                .choose(&mut self.rng)
+                .unwrap();
+                Footprint::l_shape(x, y, width, height, cut, corner)
+            }
+            70..85 if wide_enough => {
+                let side = *[Side::North, Side::South, Side::East, Side::West]
+                    .choose(&mut self.rng)
                     .unwrap();
-
-                for x in spawn_x..=spawn_x + width {
-                    for y in spawn_y..=spawn_y + height {
-                        self.is_something.insert((x, y), CellType::Building);
-                    }
-                }
-                self.is_something.remove(&(*x, *y));
-
-                self.update_borders_from_new_building(&new_building);
-                let road = if let Some((road, _)) =
-                    self.generate_road(&new_building, closest_important_building)
-                {
-                    road
-                } else {
-                    println!(
-                        "No road found between {closest_important_building:?} and {spawn_x},{spawn_y}"
-                    );
-
-                    vec![]
+                let (along, across) = match side {
+                    Side::North | Side::South => (width, height),
+                    Side::East | Side::West => (height, width),
                 };
-                for (x, y) in &road {
-                    self.is_something.insert((*x, *y), CellType::Road);
+                let bar_thickness = self.rng.gen_range(across / 3..=across / 2);
+                let stem_width = self.rng.gen_range(along / 3..=along / 2);
+                Footprint::t_shape(x, y, width, height, bar_thickness, stem_width, side)
+            }
+            85.. => {
+                let center = (
+                    f64::from(x) + f64::from(width) / 2.,
+                    f64::from(y) + f64::from(height) / 2.,
+                );
+                match self.road_direction((x + width / 2, y + height / 2)) {
+                    Some(angle) => Footprint::rotated_rectangle(center, width, height, angle),
+                    None => Footprint::rectangle(x, y, width, height),
                 }
-                self.buildings.insert((spawn_x, spawn_y), new_building);
-                self.roads.push(road);
-
-                n -= 1;
             }
+            _ => Footprint::rectangle(x, y, width, height),
         }
     }
 
-    /// Computes the borders of the city
-    fn update_borders(&mut self) {
-        self.min_x = self.buildings.values().map(|b| b.x).min().unwrap() - CITY_BOUNDS_OFFSET;
-        self.min_y = self.buildings.values().map(|b| b.y).min().unwrap() - CITY_BOUNDS_OFFSET;
+    /// Angle of the road closest to `pos`, estimated from the road cells around it
+    fn road_direction(&self, pos: (i32, i32)) -> Option<f64> {
+        let is_road = |p: (i32, i32)| matches!(self.is_something.get(&p), Some(CellType::Road));
+        // Search in growing squares around the position
+        let (road_x, road_y) = (0..=ROAD_SEARCH_RADIUS).find_map(|r| {
+            (-r..=r)
+                .flat_map(|i| [(i, -r), (i, r), (-r, i), (r, i)])
+                .map(|(i, j)| (pos.0 + i, pos.1 + j))
+                .find(|&p| is_road(p))
+        })?;
 
-        self.max_x = self
-            .buildings
-            .values()
-            .map(|b| b.x + b.width)
-            .max()
-            .unwrap()
-            + CITY_BOUNDS_OFFSET;
-        self.max_y = self
-            .buildings
-            .values()
-            .map(|b| b.y + b.height)
-            .max()
-            .unwrap()
-            + CITY_BOUNDS_OFFSET;
+        let cells = (-4..=4)
+            .flat_map(|i| (-4..=4).map(move |j| (road_x + i, road_y + j)))
+            .filter(|&p| is_road(p))
+            .map(|(x, y)| (f64::from(x - road_x), f64::from(y - road_y)))
+            .collect::<Vec<_>>();
+        if cells.len() < 3 {
+            return None;
+        }
+        // Main axis of the cells
+        #[allow(clippy::cast_precision_loss)]
+        let n = cells.len() as f64;
+        let mean_x = cells.iter().map(|c| c.0).sum::<f64>() / n;
+        let mean_y = cells.iter().map(|c| c.1).sum::<f64>() / n;
+        let (sxx, syy, sxy) = cells.iter().fold((0., 0., 0.), |(sxx, syy, sxy), (x, y)| {
+            let (dx, dy) = (x - mean_x, y - mean_y);
+            (
+                dx.mul_add(dx, sxx),
+                dy.mul_add(dy, syy),
+                dx.mul_add(dy, sxy),
+            )
+        });
+        Some(0.5 * libm::atan2(2. * sxy, sxx - syy))
+    }
+
+    /// Computes the borders of the city, they are left untouched if there is no building
+    fn update_borders(&mut self) {
+        let (Some(min_x), Some(min_y), Some(max_x), Some(max_y)) = (
+            self.buildings.values().map(|b| b.x).min(),
+            self.buildings.values().map(|b| b.y).min(),
+            self.buildings.values().map(|b| b.x + b.width).max(),
+            self.buildings.values().map(|b| b.y + b.height).max(),
+        ) else {
+            return;
+        };
+        self.min_x = min_x - CITY_BOUNDS_OFFSET;
+        self.min_y = min_y - CITY_BOUNDS_OFFSET;
+        self.max_x = max_x + CITY_BOUNDS_OFFSET;
+        self.max_y = max_y + CITY_BOUNDS_OFFSET;
     }
 
     /// Update the borders of the city based on a new building
@@ -422,42 +731,28 @@ impl CityGenerator {
     }
 
     fn successors(&self, p: (i32, i32)) -> Vec<((i32, i32), i32)> {
-        let (x, y) = p;
-
-        let mut successors = vec![];
-        for i in -1..=1 {
-            for j in -1..=1 {
-                // Don't go diagonally
-                if i != 0 && j != 0 {
-                    continue;
-                }
-
-                // Don't go back to the same point
-                // Don't go out of known bounds
-                if i == 0 && j == 0
-                    || (x + i < self.min_x
-                        || x + i >= self.max_x
-                        || y + j < self.min_y
-                        || y + j >= self.max_y)
-                {
-                    continue;
-                }
+        let mut successors = Vec::with_capacity(4);
+        // Don't go diagonally
+        for (x, y) in neighbours(p) {
+            // Don't go out of known bounds
+            if x < self.min_x || x >= self.max_x || y < self.min_y || y >= self.max_y {
+                continue;
+            }
 
-                let base_score = if i != 0 && j != 0 { 14 } else { 10 }; // if we go diagonally, the cost is sqrt(2)
-
-                match self.is_something.get(&(x + i, y + j)) {
-                    Some(CellType::Building) => match self.buildings.get(&(x + i, y + j)) {
-                        Some(building) => {
-                            // if we are in the door of the building, we can go through
-                            if building.door == (x + i, y + j) {
-                                successors.push(((x + i, y + j), base_score));
-                            }
-                        }
-                        None => continue,
-                    },
-                    Some(CellType::Road) => successors.push(((x + i, y + j), base_score)),
-                    None => successors.push(((x + i, y + j), base_score * 5)), // penalize going through nothing
+            match self.is_something.get(&(x, y)) {
+                Some(CellType::Building) => {
+                    // if we are in the door of the building, we can go through
+                    if self
+                        .buildings
+                        .get(&(x, y))
+                        .is_some_and(|building| building.doors.contains(&(x, y)))
+                    {
+                        successors.push(((x, y), ROAD_COST));
+                    }
                 }
+                Some(CellType::Road) => successors.push(((x, y), ROAD_COST)),
+                Some(CellType::Wall) => {}
+                None => successors.push(((x, y), NEW_ROAD_COST)), // penalize going through nothing
             }
         }
 
@@ -467,32 +762,80 @@ impl CityGenerator {
     /// Wrapper around A* to generate a road between two buildings
     /// Road will start at the door of the first building and end as close as possible to the second
     /// building or an existing road
-    #[allow(clippy::cast_possible_truncation)]
+    ///
+    /// The road is first searched close to both buildings, then in the whole city.
     fn generate_road(&self, start: &Building, end: &Building) -> Option<(Vec<(i32, i32)>, i32)> {
-        let (x2, y2) = start.door;
+        let door = start.door();
+        let (min_x, min_y, max_x, max_y) = end.footprint.bounding_box();
+        // Cost of a new road to the second building, it leads straight to it instead of
+        // exploring every way to reach an existing road
+        let heuristic = |&(x, y): &(i32, i32)| {
+            ((min_x - x).max(x - max_x).max(0) + (min_y - y).max(y - max_y).max(0)) * NEW_ROAD_COST
+        };
+        let success = |&p: &(i32, i32)| {
+            matches!(self.is_something.get(&p), Some(CellType::Road)) || end.contains(p)
+        };
+        let window = (
+            door.0.min(min_x) - ROAD_SEARCH_MARGIN,
+            door.1.min(min_y) - ROAD_SEARCH_MARGIN,
+            door.0.max(max_x) + ROAD_SEARCH_MARGIN,
+            door.1.max(max_y) + ROAD_SEARCH_MARGIN,
+        );
         astar(
-            &(x2, y2),
-            |&p| self.successors(p),
+            &door,
             |&p| {
-                let (x, y) = p;
-                f64::from(((x - end.x + end.width).abs() + (y - end.y + end.height).abs()) * 10)
-                    .sqrt() as i32
+                let mut successors = self.successors(p);
+                successors.retain(|((x, y), _)| {
+                    (window.0..=window.2).contains(x) && (window.1..=window.3).contains(y)
+                });
+                successors
             },
-            |&p| matches!(self.is_something.get(&p), Some(CellType::Road)) || end.contains(p),
+            heuristic,
+            success,
         )
+        .or_else(|| astar(&door, |&p| self.successors(p), heuristic, success))
     }
 }
+
+/// Multiply the coordinates of a road by `factor`, filling the gaps between its cells
+fn scale_road(road: &[(i32, i32)], factor: i32) -> Vec<(i32, i32)> {
+    let mut scaled_road = vec![];
+    for window in road.windows(2) {
+        let (from, to) = (window[0], window[1]);
+        let direction = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+        let mut position = (from.0 * factor, from.1 * factor);
+        for _ in 0..factor {
+            scaled_road.push(position);
+            position = (position.0 + direction.0, position.1 + direction.1);
+        }
+    }
+    scaled_road
+}
+
 #[cfg(test)]
 mod tests {
-    use super::CityGenerator;
+    use super::{
+        line_cells, neighbours, scale_road, CellType, CityConfig, CityGenError, CityGenerator,
+        LayoutMode,
+    };
+    use std::fmt::Write;
+
+    fn config() -> CityConfig {
+        CityConfig::default()
+            .set_width_bound(10..30)
+            .set_height_bound(10..30)
+    }
 
     #[test]
     fn test_different_seeds() {
-        let seed = 1;
-        let mut city_gen1 = CityGenerator::new(seed, 10..30, 10..30, 20..100, 1000);
-        let mut city_gen2 = CityGenerator::new(seed, 10..30, 10..30, 20..100, 1000);
-        city_gen1.generate(100, 6, 10);
-        city_gen2.generate(100, 6, 10);
+        let config = config()
+            .set_seed(1)
+            .set_buildings(100)
+            .set_important_buildings(6);
+        let mut city_gen1 = CityGenerator::new(config.clone());
+        let mut city_gen2 = CityGenerator::new(config);
+        city_gen1.generate().unwrap();
+        city_gen2.generate().unwrap();
 
         assert_eq!(city_gen1.min_x, city_gen2.min_x);
         assert_eq!(city_gen1.min_y, city_gen2.min_y);
@@ -500,4 +843,191 @@ mod tests {
         assert_eq!(city_gen1.max_y, city_gen2.max_y);
         assert_eq!(city_gen1.buildings, city_gen2.buildings);
     }
+
+    #[test]
+    fn test_street_first_buildings_face_streets() {
+        let config = CityConfig::default()
+            .set_seed(2)
+            .set_buildings(80)
+            .set_important_buildings(3)
+            .set_width_bound(8..20)
+            .set_height_bound(8..20)
+            .set_important_buildings_max_distance(600)
+            .set_layout(LayoutMode::StreetFirst);
+        let mut city_gen = CityGenerator::new(config.clone());
+        city_gen.generate().unwrap();
+        // The same streets without the lots and the paths to their doors
+        let mut streets_gen = CityGenerator::new(config);
+        streets_gen.generate_streets(83);
+        let streets = streets_gen.roads.concat();
+        assert_eq!(city_gen.roads[..streets_gen.roads.len()], streets_gen.roads);
+
+        assert_eq!(city_gen.buildings.len(), 83);
+        assert_eq!(city_gen.important_buildings.len(), 3);
+        // Every door is linked to a street by a path going straight to it
+        let is_something = &city_gen.is_something;
+        for building in city_gen.buildings.values() {
+            let door = building.door();
+            assert!(neighbours(door).into_iter().any(|outside| {
+                streets.iter().any(|street| {
+                    line_cells(outside, *street)
+                        .iter()
+                        .all(|pos| matches!(is_something.get(pos), Some(CellType::Road)))
+                })
+            }));
+        }
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let mut city_gen = CityGenerator::new(config().set_width_bound(10..10));
+        assert_eq!(
+            city_gen.generate(),
+            Err(CityGenError::InvalidRange("width_bound"))
+        );
+        assert!(city_gen.buildings.is_empty());
+    }
+
+    #[test]
+    fn test_placement_failed() {
+        // Room for a few important buildings only
+        let mut city_gen = CityGenerator::new(config().set_important_buildings_max_distance(40));
+        assert!(matches!(
+            city_gen.generate_important_buildings(20, 1),
+            Err(CityGenError::PlacementFailed { requested: 20, .. })
+        ));
+
+        // Normal buildings spawned right next to the others always overlap them
+        let mut city_gen = CityGenerator::new(
+            config()
+                .set_width_bound(10..11)
+                .set_height_bound(10..11)
+                .set_distance_bound(10..11)
+                .set_buildings(5)
+                .set_important_buildings(1),
+        );
+        assert_eq!(
+            city_gen.generate(),
+            Err(CityGenError::PlacementFailed {
+                placed: 0,
+                requested: 5
+            })
+        );
+
+        // Streets don't go further than half the max distance, there are not enough lots
+        let mut city_gen = CityGenerator::new(
+            config()
+                .set_layout(LayoutMode::StreetFirst)
+                .set_important_buildings_max_distance(100)
+                .set_buildings(50),
+        );
+        let Err(CityGenError::PlacementFailed { placed, requested }) = city_gen.generate() else {
+            panic!("the street-first city should run out of lots");
+        };
+        assert_eq!(requested, 54);
+        assert!(placed < requested);
+        assert_eq!(city_gen.buildings.len(), placed);
+    }
+
+    /// FNV-1a hash of everything generated, independent of the platform and of the std hasher
+    fn city_hash(city_gen: &CityGenerator) -> u64 {
+        let mut values = vec![
+            i64::from(city_gen.min_x),
+            i64::from(city_gen.min_y),
+            i64::from(city_gen.max_x),
+            i64::from(city_gen.max_y),
+        ];
+        fn push_cells(values: &mut Vec<i64>, cells: &[(i32, i32)]) {
+            values.push(cells.len() as i64);
+            values.extend(
+                cells
+                    .iter()
+                    .flat_map(|(x, y)| [i64::from(*x), i64::from(*y)]),
+            );
+        }
+        for building in city_gen.buildings.values() {
+            values.extend([
+                building.id as i64,
+                i64::from(building.is_important),
+                building.building_type as i64,
+            ]);
+            push_cells(&mut values, &building.doors);
+            push_cells(&mut values, &building.footprint.vertices);
+        }
+        push_cells(&mut values, &city_gen.important_buildings);
+        values.push(city_gen.main_roads as i64);
+        for road in &city_gen.roads {
+            push_cells(&mut values, road);
+        }
+        if let Some(wall) = &city_gen.wall {
+            for cells in [&wall.outline, &wall.cells, &wall.towers, &wall.gates] {
+                push_cells(&mut values, cells);
+            }
+        }
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    /// Cities must stay the same for a given configuration, on every platform and from one
+    /// version to the next
+    ///
+    /// The trigonometry goes through `libm` rather than the platform's math library, whose last
+    /// bit may differ. Run with `UPDATE_GOLDEN=1` to accept intended changes.
+    #[test]
+    fn test_golden_hashes() {
+        let cases = [
+            ("building-first-0", config().set_seed(0), None),
+            ("building-first-1", config().set_seed(1), None),
+            (
+                "building-first-2",
+                config().set_seed(2).set_subdivide_blocks(true),
+                None,
+            ),
+            (
+                "street-first-3",
+                config().set_seed(3).set_layout(LayoutMode::StreetFirst),
+                None,
+            ),
+            (
+                "street-first-blocks-4",
+                config()
+                    .set_seed(4)
+                    .set_layout(LayoutMode::StreetFirst)
+                    .set_grid_bias(1.)
+                    .set_subdivide_blocks(true),
+                None,
+            ),
+            ("walled-grown-5", config().set_seed(5), Some((10, 5))),
+        ];
+        let mut hashes = String::new();
+        for (name, config, wall_and_years) in cases {
+            let mut city_gen = CityGenerator::new(config);
+            city_gen.generate().unwrap();
+            if let Some((padding, years)) = wall_and_years {
+                city_gen.generate_wall(padding);
+                city_gen.step(years).unwrap();
+            }
+            writeln!(hashes, "{name} {:016x}", city_hash(&city_gen)).unwrap();
+        }
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/city_hashes.txt");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(path, &hashes).unwrap();
+        }
+        assert_eq!(hashes, std::fs::read_to_string(path).unwrap());
+    }
+
+    #[test]
+    fn test_scale_road() {
+        assert!(scale_road(&[], 4).is_empty());
+        assert!(scale_road(&[(1, 1)], 4).is_empty());
+        assert_eq!(
+            scale_road(&[(0, 0), (1, 0), (1, 1)], 2),
+            vec![(0, 0), (1, 0), (2, 0), (2, 1)]
+        );
+    }
 }
@@ -0,0 +1,281 @@
+use rand::Rng;
+use std::{collections::HashSet, f64::consts::FRAC_PI_2};
+
+use super::{neighbours, Building, CellType, CityGenerator, Footprint};
+
+/// Free space kept between a building and the limits of its lot
+const LOT_MARGIN: i32 = 2;
+/// Attempts to fit a building in a lot, shrinking it every time
+const LOT_FIT_ATTEMPTS: i32 = 3;
+
+/// Block of the city, an area enclosed by roads
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    /// Free cells of the block, sorted
+    pub cells: Vec<(i32, i32)>,
+    /// Road cells along the block, sorted
+    pub frontage: Vec<(i32, i32)>,
+}
+
+impl CityGenerator {
+    /// Areas enclosed by roads, the open space around the city is not a block
+    #[must_use]
+    pub fn blocks(&self) -> Vec<Block> {
+        let road_cells = self
+            .roads
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|pos| self.is_road(*pos))
+            .collect::<HashSet<_>>();
+        let Some(&(x, y)) = road_cells.iter().next() else {
+            return vec![];
+        };
+        let (min_x, min_y, max_x, max_y) =
+            road_cells
+                .iter()
+                .fold((x, y, x, y), |(min_x, min_y, max_x, max_y), (x, y)| {
+                    (min_x.min(*x), min_y.min(*y), max_x.max(*x), max_y.max(*y))
+                });
+        let mut seeds = road_cells
+            .iter()
+            .flat_map(|pos| neighbours(*pos))
+            .filter(|pos| !self.is_something.contains_key(pos))
+            .collect::<Vec<_>>();
+        seeds.sort_unstable();
+        seeds.dedup();
+
+        let mut visited = HashSet::new();
+        let mut blocks = vec![];
+        for seed in seeds {
+            if !visited.insert(seed) {
+                continue;
+            }
+            // Flood fill, reaching the limits of the road network means the area is open
+            let mut cells = vec![];
+            let mut stack = vec![seed];
+            let mut enclosed = true;
+            while let Some(pos) = stack.pop() {
+                cells.push(pos);
+                for next in neighbours(pos) {
+                    if self.is_something.contains_key(&next) || visited.contains(&next) {
+                        continue;
+                    }
+                    if next.0 <= min_x || next.0 >= max_x || next.1 <= min_y || next.1 >= max_y {
+                        enclosed = false;
+                        continue;
+                    }
+                    visited.insert(next);
+                    stack.push(next);
+                }
+            }
+            if enclosed {
+                cells.sort_unstable();
+                let mut frontage = cells
+                    .iter()
+                    .flat_map(|pos| neighbours(*pos))
+                    .filter(|pos| self.is_road(*pos))
+                    .collect::<Vec<_>>();
+                frontage.sort_unstable();
+                frontage.dedup();
+                blocks.push(Block { cells, frontage });
+            }
+        }
+        blocks
+    }
+
+    /// Subdivide the blocks in lots and build one building per lot, closest to the center first
+    ///
+    /// The first buildings are important ones. Returns the number of buildings placed, which is
+    /// lower than asked when the blocks are full.
+    pub(super) fn generate_block_buildings(
+        &mut self,
+        normal_buildings: usize,
+        important_buildings: usize,
+    ) -> usize {
+        let mut lots = vec![];
+        for block in self.blocks() {
+            let angle = self.block_direction(&block.cells, &block.frontage);
+            self.split_lot(block.cells, angle, &mut lots);
+        }
+        lots.sort_by_key(|lot| {
+            let (x, y) = mean(lot);
+            i64::from(x).pow(2) + i64::from(y).pow(2)
+        });
+
+        let mut placed = 0;
+        for lot in lots {
+            if placed == normal_buildings + important_buildings {
+                break;
+            }
+            if let Some(building) = self.lot_building_in(&lot) {
+                self.add_lot_building(building, placed < important_buildings);
+                placed += 1;
+            }
+        }
+        placed
+    }
+
+    /// Recursively split a lot perpendicularly to its longest side while both parts have frontage
+    #[allow(clippy::cast_sign_loss)]
+    fn split_lot(&mut self, cells: Vec<(i32, i32)>, angle: f64, lots: &mut Vec<Vec<(i32, i32)>>) {
+        let min_frontage = self.config.width_bound.start as usize;
+        if self.frontage(&cells) == 0 {
+            return;
+        }
+        let max_area = (self.config.width_bound.end * self.config.height_bound.end) as usize;
+        if cells.len() <= max_area {
+            lots.push(cells);
+            return;
+        }
+
+        let extent = |axis: f64| {
+            let (sin, cos) = libm::sincos(axis);
+            cells
+                .iter()
+                .map(|(x, y)| f64::from(*x).mul_add(cos, f64::from(*y) * sin))
+                .fold((f64::MAX, f64::MIN), |(min, max), t| {
+                    (min.min(t), max.max(t))
+                })
+        };
+        let mut axes = [angle, angle + FRAC_PI_2].map(|axis| (axis, extent(axis)));
+        axes.sort_by(|(_, (min1, max1)), (_, (min2, max2))| {
+            (max2 - min2).total_cmp(&(max1 - min1))
+        });
+
+        for (axis, (min, max)) in axes {
+            let (sin, cos) = libm::sincos(axis);
+            let split = (max - min).mul_add(self.rng.gen_range(0.4..0.6), min);
+            let (first, second): (Vec<_>, Vec<_>) = cells
+                .iter()
+                .partition(|(x, y)| f64::from(*x).mul_add(cos, f64::from(*y) * sin) < split);
+            if self.frontage(&first) >= min_frontage && self.frontage(&second) >= min_frontage {
+                self.split_lot(first, angle, lots);
+                self.split_lot(second, angle, lots);
+                return;
+            }
+        }
+        // Can't be split without landlocking a part
+        lots.push(cells);
+    }
+
+    /// Building filling a lot, aligned with and facing its closest road
+    #[allow(clippy::cast_possible_truncation)]
+    fn lot_building_in(&mut self, lot: &[(i32, i32)]) -> Option<Building> {
+        let center = mean(lot);
+        let street = lot
+            .iter()
+            .flat_map(|pos| neighbours(*pos))
+            .filter(|pos| self.is_road(*pos))
+            .min_by_key(|(x, y)| ((x - center.0).pow(2) + (y - center.1).pow(2), *x, *y))?;
+        let angle = self.road_direction(street).unwrap_or(0.);
+
+        // Extent of the lot in the frame of the road
+        let (sin, cos) = libm::sincos(angle);
+        let (u_min, u_max, v_min, v_max) = lot.iter().fold(
+            (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+            |(u_min, u_max, v_min, v_max), (x, y)| {
+                let (dx, dy) = (f64::from(x - center.0), f64::from(y - center.1));
+                let (u, v) = (dx.mul_add(cos, dy * sin), dy.mul_add(cos, -dx * sin));
+                (u_min.min(u), u_max.max(u), v_min.min(v), v_max.max(v))
+            },
+        );
+        let (mid_u, mid_v) = (f64::midpoint(u_min, u_max), f64::midpoint(v_min, v_max));
+        let middle = (
+            f64::from(center.0) + mid_u * cos - mid_v * sin,
+            f64::from(center.1) + mid_u * sin + mid_v * cos,
+        );
+        let width = ((u_max - u_min) as i32 - 2 * LOT_MARGIN).min(self.config.width_bound.end - 1);
+        let height =
+            ((v_max - v_min) as i32 - 2 * LOT_MARGIN).min(self.config.height_bound.end - 1);
+
+        let lot_cells = lot.iter().copied().collect::<HashSet<_>>();
+        for shrink in 0..LOT_FIT_ATTEMPTS {
+            let (width, height) = (width - 2 * shrink, height - 2 * shrink);
+            if width < 3 || height < 3 {
+                return None;
+            }
+            let footprint = Footprint::rotated_rectangle(middle, width, height, angle);
+            if !footprint.cells().iter().all(|pos| lot_cells.contains(pos)) {
+                continue;
+            }
+            if let Some(building) = self.lot_building(footprint, street) {
+                return Some(building);
+            }
+        }
+        None
+    }
+
+    /// Direction of the road closest to the center of the block
+    fn block_direction(&self, cells: &[(i32, i32)], frontage: &[(i32, i32)]) -> f64 {
+        let center = mean(cells);
+        frontage
+            .iter()
+            .min_by_key(|(x, y)| (x - center.0).pow(2) + (y - center.1).pow(2))
+            .and_then(|street| self.road_direction(*street))
+            .unwrap_or(0.)
+    }
+
+    /// Number of cells next to a road
+    fn frontage(&self, cells: &[(i32, i32)]) -> usize {
+        cells
+            .iter()
+            .filter(|pos| neighbours(**pos).iter().any(|n| self.is_road(*n)))
+            .count()
+    }
+
+    fn is_road(&self, pos: (i32, i32)) -> bool {
+        matches!(self.is_something.get(&pos), Some(CellType::Road))
+    }
+}
+
+/// Average position of some cells
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn mean(cells: &[(i32, i32)]) -> (i32, i32) {
+    let n = cells.len().max(1) as i64;
+    let (sx, sy) = cells.iter().fold((0i64, 0i64), |(sx, sy), (x, y)| {
+        (sx + i64::from(*x), sy + i64::from(*y))
+    });
+    ((sx / n) as i32, (sy / n) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{CellType, CityConfig, CityGenerator};
+
+    #[test]
+    fn test_blocks() {
+        let mut city_gen = CityGenerator::new(
+            CityConfig::default()
+                .set_width_bound(8..12)
+                .set_height_bound(8..12),
+        );
+        // Two squares sharing a side, with a dead end going out
+        let road = [
+            ((0, 0), (60, 0)),
+            ((60, 0), (60, 30)),
+            ((60, 30), (0, 30)),
+            ((0, 30), (0, 0)),
+            ((30, 0), (30, 30)),
+            ((60, 15), (80, 15)),
+        ]
+        .into_iter()
+        .map(|(from, to)| super::super::line_cells(from, to))
+        .collect::<Vec<_>>();
+        for pos in road.iter().flatten() {
+            city_gen.is_something.insert(*pos, CellType::Road);
+        }
+        city_gen.roads = road;
+
+        let blocks = city_gen.blocks();
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks.iter().all(|block| block.cells.len() == 29 * 29));
+
+        city_gen.generate_block_buildings(100, 0);
+        assert!(city_gen.buildings.len() >= 4);
+        for building in city_gen.buildings.values() {
+            let (x, y) = building.door();
+            assert!((1..60).contains(&x) && (1..30).contains(&y));
+        }
+    }
+}
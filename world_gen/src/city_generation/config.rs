@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use super::{CityGenError, LayoutMode};
+
+/// Margin kept around important buildings when they are placed
+pub(super) const IMPORTANT_BUILDING_MARGIN: i32 = 3;
+
+/// Parameters of the city generation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CityConfig {
+    /// Seed of the random generator
+    pub seed: u64,
+    /// Number of normal buildings
+    pub buildings: usize,
+    /// Number of important buildings
+    pub important_buildings: usize,
+    /// Important buildings are placed and linked on a grid this many times smaller, then scaled up
+    pub important_building_scale: i32,
+    /// Min and max width of the buildings
+    pub width_bound: Range<i32>,
+    /// Min and max height of the buildings
+    pub height_bound: Range<i32>,
+    /// Min and max distance between buildings
+    pub distance_bound: Range<i32>,
+    /// Max distance between important buildings
+    pub important_buildings_max_distance: i32,
+    /// Whether buildings or streets are placed first
+    pub layout: LayoutMode,
+    /// How much the streets follow a grid, from 0 (organic) to 1 (grid)
+    pub grid_bias: f64,
+    /// If normal buildings are placed on lots subdividing the blocks
+    pub subdivide_blocks: bool,
+}
+
+impl Default for CityConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            buildings: 100,
+            important_buildings: 4,
+            important_building_scale: 10,
+            width_bound: 8..30,
+            height_bound: 8..30,
+            distance_bound: 20..100,
+            important_buildings_max_distance: 1000,
+            layout: LayoutMode::default(),
+            grid_bias: 0.5,
+            subdivide_blocks: false,
+        }
+    }
+}
+
+impl CityConfig {
+    #[must_use]
+    pub fn set_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+    #[must_use]
+    pub fn set_buildings(self, buildings: usize) -> Self {
+        Self { buildings, ..self }
+    }
+    #[must_use]
+    pub fn set_important_buildings(self, important_buildings: usize) -> Self {
+        Self {
+            important_buildings,
+            ..self
+        }
+    }
+    /// Only used by the building first layout
+    #[must_use]
+    pub fn set_important_building_scale(self, important_building_scale: i32) -> Self {
+        Self {
+            important_building_scale,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_width_bound(self, width_bound: Range<i32>) -> Self {
+        Self {
+            width_bound,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_height_bound(self, height_bound: Range<i32>) -> Self {
+        Self {
+            height_bound,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_distance_bound(self, distance_bound: Range<i32>) -> Self {
+        Self {
+            distance_bound,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_important_buildings_max_distance(
+        self,
+        important_buildings_max_distance: i32,
+    ) -> Self {
+        Self {
+            important_buildings_max_distance,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_layout(self, layout: LayoutMode) -> Self {
+        Self { layout, ..self }
+    }
+    /// Only used by the street first layout, from 0 (organic) to 1 (grid)
+    #[must_use]
+    pub fn set_grid_bias(self, grid_bias: f64) -> Self {
+        Self { grid_bias, ..self }
+    }
+    /// Place the buildings on lots subdividing the blocks enclosed by roads instead of spawning
+    /// them around existing buildings, buildings that don't fit in the blocks are placed as usual
+    #[must_use]
+    pub fn set_subdivide_blocks(self, subdivide_blocks: bool) -> Self {
+        Self {
+            subdivide_blocks,
+            ..self
+        }
+    }
+
+    /// Check that a city can be generated with these parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns the first invalid parameter found.
+    pub fn validate(&self) -> Result<(), CityGenError> {
+        for (name, range) in [
+            ("width_bound", &self.width_bound),
+            ("height_bound", &self.height_bound),
+            ("distance_bound", &self.distance_bound),
+        ] {
+            if range.is_empty() || range.start < 1 {
+                return Err(CityGenError::InvalidRange(name));
+            }
+        }
+        // Buildings spawned closer than their size always overlap
+        let size = self.width_bound.start.max(self.height_bound.start);
+        if self.distance_bound.start < size {
+            return Err(CityGenError::DistanceTooSmall {
+                distance: self.distance_bound.start,
+                size,
+            });
+        }
+        if !(0. ..=1.).contains(&self.grid_bias) {
+            return Err(CityGenError::InvalidGridBias(self.grid_bias));
+        }
+        if self.buildings + self.important_buildings == 0 {
+            return Err(CityGenError::NoBuildings);
+        }
+        if self.layout == LayoutMode::StreetFirst {
+            return Ok(());
+        }
+        if self.important_buildings == 0 {
+            return Err(CityGenError::NoImportantBuildings);
+        }
+        let scale = self.important_building_scale;
+        if scale < 1 {
+            return Err(CityGenError::InvalidScale(scale));
+        }
+        // Important buildings are spread over a square, on average they must fit in it
+        let side = i64::from(self.important_buildings_max_distance / scale);
+        let mean_size = |range: &Range<i32>| {
+            i64::from((range.start + range.end) / 2 / scale + 1 + IMPORTANT_BUILDING_MARGIN)
+        };
+        let needed = (mean_size(&self.width_bound) * mean_size(&self.height_bound))
+            .saturating_mul(i64::try_from(self.important_buildings).unwrap_or(i64::MAX));
+        if side < 2 || side * side < needed {
+            return Err(CityGenError::MaxDistanceTooSmall {
+                max_distance: self.important_buildings_max_distance,
+                scale,
+                important_buildings: self.important_buildings,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CityConfig, CityGenError, LayoutMode};
+
+    #[test]
+    fn test_default_is_valid() {
+        assert_eq!(CityConfig::default().validate(), Ok(()));
+        let json = serde_json::to_string(&CityConfig::default()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<CityConfig>(&json).unwrap(),
+            CityConfig::default()
+        );
+        // Missing fields take their default value
+        let config = serde_json::from_str::<CityConfig>(
+            r#"{"seed": 3, "width_bound": {"start": 5, "end": 10}, "layout": "street-first"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            CityConfig::default()
+                .set_seed(3)
+                .set_width_bound(5..10)
+                .set_layout(LayoutMode::StreetFirst)
+        );
+    }
+
+    #[test]
+    fn test_invalid_range() {
+        assert_eq!(
+            CityConfig::default().set_width_bound(10..10).validate(),
+            Err(CityGenError::InvalidRange("width_bound"))
+        );
+        assert_eq!(
+            CityConfig::default().set_distance_bound(0..100).validate(),
+            Err(CityGenError::InvalidRange("distance_bound"))
+        );
+    }
+
+    #[test]
+    fn test_distance_too_small() {
+        assert_eq!(
+            CityConfig::default()
+                .set_height_bound(30..40)
+                .set_distance_bound(20..100)
+                .validate(),
+            Err(CityGenError::DistanceTooSmall {
+                distance: 20,
+                size: 30
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_grid_bias() {
+        assert_eq!(
+            CityConfig::default().set_grid_bias(1.5).validate(),
+            Err(CityGenError::InvalidGridBias(1.5))
+        );
+    }
+
+    #[test]
+    fn test_invalid_scale() {
+        assert_eq!(
+            CityConfig::default()
+                .set_important_building_scale(0)
+                .validate(),
+            Err(CityGenError::InvalidScale(0))
+        );
+    }
+
+    #[test]
+    fn test_max_distance_too_small() {
+        let config = CityConfig::default()
+            .set_important_buildings_max_distance(40)
+            .set_important_building_scale(1);
+        assert_eq!(config.clone().set_important_buildings(1).validate(), Ok(()));
+        assert_eq!(
+            config.set_important_buildings(20).validate(),
+            Err(CityGenError::MaxDistanceTooSmall {
+                max_distance: 40,
+                scale: 1,
+                important_buildings: 20
+            })
+        );
+    }
+
+    #[test]
+    fn test_no_buildings() {
+        let config = CityConfig::default()
+            .set_buildings(0)
+            .set_important_buildings(0);
+        assert_eq!(config.validate(), Err(CityGenError::NoBuildings));
+        assert_eq!(
+            config.set_buildings(10).validate(),
+            Err(CityGenError::NoImportantBuildings)
+        );
+    }
+}
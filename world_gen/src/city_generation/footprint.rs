@@ -0,0 +1,410 @@
+/// Corner of a rectangle, north is towards negative y
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corner {
+    NorthWest,
+    NorthEast,
+    SouthEast,
+    SouthWest,
+}
+
+/// Side of a rectangle, north is towards negative y
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    North,
+    South,
+    East,
+    West,
+}
+
+/// Ground shape of a building, a simple polygon with integer vertices
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Footprint {
+    /// Vertices of the polygon, in order, the last one is connected to the first one
+    pub vertices: Vec<(i32, i32)>,
+}
+
+impl Footprint {
+    /// Axis-aligned rectangle with `(x, y)` as top left corner
+    #[must_use]
+    pub fn rectangle(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            vertices: vec![
+                (x, y),
+                (x + width, y),
+                (x + width, y + height),
+                (x, y + height),
+            ],
+        }
+    }
+
+    /// Rectangle with a `cut` sized rectangle removed from one of its corners
+    #[must_use]
+    pub fn l_shape(
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        cut: (i32, i32),
+        corner: Corner,
+    ) -> Self {
+        let (cut_w, cut_h) = cut;
+        // Built with the north east corner cut, then mirrored
+        let vertices = vec![
+            (0, 0),
+            (width - cut_w, 0),
+            (width - cut_w, cut_h),
+            (width, cut_h),
+            (width, height),
+            (0, height),
+        ];
+        let (mirror_x, mirror_y) = match corner {
+            Corner::NorthEast => (false, false),
+            Corner::NorthWest => (true, false),
+            Corner::SouthEast => (false, true),
+            Corner::SouthWest => (true, true),
+        };
+        Self {
+            vertices: vertices
+                .into_iter()
+                .map(|(u, v)| {
+                    (
+                        x + if mirror_x { width - u } else { u },
+                        y + if mirror_y { height - v } else { v },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Bar along one `side` of the rectangle with a centered stem going to the opposite side
+    #[must_use]
+    pub fn t_shape(
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        bar_thickness: i32,
+        stem_width: i32,
+        side: Side,
+    ) -> Self {
+        // Built with the bar on the north side in a frame where `along` is the bar length
+        let (along, across) = match side {
+            Side::North | Side::South => (width, height),
+            Side::East | Side::West => (height, width),
+        };
+        let stem_start = (along - stem_width) / 2;
+        let stem_end = stem_start + stem_width;
+        let vertices = vec![
+            (0, 0),
+            (along, 0),
+            (along, bar_thickness),
+            (stem_end, bar_thickness),
+            (stem_end, across),
+            (stem_start, across),
+            (stem_start, bar_thickness),
+            (0, bar_thickness),
+        ];
+        Self {
+            vertices: vertices
+                .into_iter()
+                .map(|(u, v)| match side {
+                    Side::North => (x + u, y + v),
+                    Side::South => (x + u, y + height - v),
+                    Side::West => (x + v, y + u),
+                    Side::East => (x + width - v, y + u),
+                })
+                .collect(),
+        }
+    }
+
+    /// Rectangle rotated by `angle` radians around `center`, vertices are rounded to the grid
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn rotated_rectangle(center: (f64, f64), width: i32, height: i32, angle: f64) -> Self {
+        let (sin, cos) = libm::sincos(angle);
+        let (half_w, half_h) = (f64::from(width) / 2., f64::from(height) / 2.);
+        Self {
+            vertices: [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)]
+                .into_iter()
+                .map(|(sx, sy): (f64, f64)| {
+                    let (u, v) = (sx * half_w, sy * half_h);
+                    (
+                        (center.0 + u * cos - v * sin).round() as i32,
+                        (center.1 + u * sin + v * cos).round() as i32,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Multiply every coordinate by `factor`
+    #[must_use]
+    pub fn scaled(&self, factor: i32) -> Self {
+        Self {
+            vertices: self
+                .vertices
+                .iter()
+                .map(|(x, y)| (x * factor, y * factor))
+                .collect(),
+        }
+    }
+
+    /// Returns `(min_x, min_y, max_x, max_y)`
+    #[must_use]
+    pub fn bounding_box(&self) -> (i32, i32, i32, i32) {
+        self.vertices.iter().fold(
+            (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+            |(min_x, min_y, max_x, max_y), &(x, y)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            },
+        )
+    }
+
+    /// Area of the polygon
+    #[must_use]
+    pub fn area(&self) -> i32 {
+        self.edges()
+            .map(|((x1, y1), (x2, y2))| x1 * y2 - x2 * y1)
+            .sum::<i32>()
+            .abs()
+            / 2
+    }
+
+    /// Iterate over the edges of the polygon
+    pub fn edges(&self) -> impl Iterator<Item = ((i32, i32), (i32, i32))> + '_ {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(&a, &b)| (a, b))
+    }
+
+    /// Check if a point is inside the polygon (including its walls)
+    #[must_use]
+    pub fn contains(&self, pos: (i32, i32)) -> bool {
+        let (min_x, min_y, max_x, max_y) = self.bounding_box();
+        if pos.0 < min_x || pos.0 > max_x || pos.1 < min_y || pos.1 > max_y {
+            return false;
+        }
+        if self.edges().any(|(a, b)| on_segment(pos, a, b)) {
+            return true;
+        }
+        // Even-odd rule, casting a ray towards positive x
+        let (px, py) = (f64::from(pos.0), f64::from(pos.1));
+        let mut inside = false;
+        for ((x1, y1), (x2, y2)) in self.edges() {
+            let (x1, y1, x2, y2) = (f64::from(x1), f64::from(y1), f64::from(x2), f64::from(y2));
+            if (y1 > py) != (y2 > py) && px < (x2 - x1) * (py - y1) / (y2 - y1) + x1 {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Every grid cell covered by the building, walls included
+    #[must_use]
+    pub fn cells(&self) -> Vec<(i32, i32)> {
+        let mask = Mask::new(self);
+        mask.cells().collect()
+    }
+
+    /// Cells of the walls, they have at least one neighbour outside of the building
+    #[must_use]
+    pub fn boundary_cells(&self) -> Vec<(i32, i32)> {
+        let mask = Mask::new(self);
+        mask.cells()
+            .filter(|&pos| mask.outside_neighbours(pos) > 0)
+            .collect()
+    }
+
+    /// Cells where a door can be placed: walls that are not corners
+    #[must_use]
+    pub fn door_candidates(&self) -> Vec<(i32, i32)> {
+        let mask = Mask::new(self);
+        let mut candidates = mask
+            .cells()
+            .filter(|&pos| mask.outside_neighbours(pos) == 1)
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates
+    }
+
+    /// Check if two polygons are closer than `offset`
+    #[must_use]
+    pub fn overlaps(&self, other: &Self, offset: i32) -> bool {
+        let (min_x, min_y, max_x, max_y) = self.bounding_box();
+        let (o_min_x, o_min_y, o_max_x, o_max_y) = other.bounding_box();
+        // Cheap bounding box check first
+        if !(min_x - offset < o_max_x
+            && max_x + offset > o_min_x
+            && min_y - offset < o_max_y
+            && max_y + offset > o_min_y)
+        {
+            return false;
+        }
+        if self.contains(other.vertices[0]) || other.contains(self.vertices[0]) {
+            return true;
+        }
+        let offset = f64::from(offset);
+        self.edges().any(|(a, b)| {
+            other.edges().any(|(c, d)| {
+                segments_intersect(a, b, c, d) || segments_distance(a, b, c, d) < offset
+            })
+        })
+    }
+}
+
+/// Cells of the bounding box of a footprint that are inside of it
+struct Mask {
+    min: (i32, i32),
+    width: i32,
+    height: i32,
+    inside: Vec<bool>,
+}
+
+impl Mask {
+    fn new(footprint: &Footprint) -> Self {
+        let (min_x, min_y, max_x, max_y) = footprint.bounding_box();
+        let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+        let inside = (min_y..=max_y)
+            .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)))
+            .map(|pos| footprint.contains(pos))
+            .collect();
+        Self {
+            min: (min_x, min_y),
+            width,
+            height,
+            inside,
+        }
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        let (dx, dy) = (x - self.min.0, y - self.min.1);
+        dx >= 0
+            && dy >= 0
+            && dx < self.width
+            && dy < self.height
+            && self.inside[(dy * self.width + dx) as usize]
+    }
+
+    /// Inside cells, in column order
+    fn cells(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (self.min.0..self.min.0 + self.width)
+            .flat_map(move |x| (self.min.1..self.min.1 + self.height).map(move |y| (x, y)))
+            .filter(|&pos| self.contains(pos))
+    }
+
+    fn outside_neighbours(&self, pos: (i32, i32)) -> usize {
+        neighbours(pos)
+            .iter()
+            .filter(|neighbour| !self.contains(**neighbour))
+            .count()
+    }
+}
+
+/// 4-neighbourhood of a cell
+pub(crate) const fn neighbours((x, y): (i32, i32)) -> [(i32, i32); 4] {
+    [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+}
+
+/// Cells of a 4-connected line between two points, nothing can slip through it diagonally
+pub(crate) fn line_cells(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (dx, dy) = ((to.0 - from.0).abs(), (to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (mut x, mut y) = from;
+    let mut cells = vec![from];
+    let (mut moved_x, mut moved_y) = (0, 0);
+    while moved_x < dx || moved_y < dy {
+        // Move along the axis that is the most behind
+        if (1 + 2 * moved_x) * dy < (1 + 2 * moved_y) * dx {
+            x += step_x;
+            moved_x += 1;
+        } else {
+            y += step_y;
+            moved_y += 1;
+        }
+        cells.push((x, y));
+    }
+    cells
+}
+
+/// Cross product of `b - a` and `c - a`
+fn cross(a: (i32, i32), b: (i32, i32), c: (i32, i32)) -> i64 {
+    i64::from(b.0 - a.0) * i64::from(c.1 - a.1) - i64::from(b.1 - a.1) * i64::from(c.0 - a.0)
+}
+
+/// Check if `p` lies on the segment `[a, b]`
+fn on_segment(p: (i32, i32), a: (i32, i32), b: (i32, i32)) -> bool {
+    cross(a, b, p) == 0
+        && p.0 >= a.0.min(b.0)
+        && p.0 <= a.0.max(b.0)
+        && p.1 >= a.1.min(b.1)
+        && p.1 <= a.1.max(b.1)
+}
+
+/// Check if the segments `[a, b]` and `[c, d]` share at least one point
+fn segments_intersect(a: (i32, i32), b: (i32, i32), c: (i32, i32), d: (i32, i32)) -> bool {
+    let (d1, d2) = (cross(c, d, a), cross(c, d, b));
+    let (d3, d4) = (cross(a, b, c), cross(a, b, d));
+    if d1.signum() * d2.signum() < 0 && d3.signum() * d4.signum() < 0 {
+        return true;
+    }
+    on_segment(a, c, d) || on_segment(b, c, d) || on_segment(c, a, b) || on_segment(d, a, b)
+}
+
+/// Distance between the point `p` and the segment `[a, b]`
+fn point_segment_distance(p: (i32, i32), a: (i32, i32), b: (i32, i32)) -> f64 {
+    let (px, py) = (f64::from(p.0), f64::from(p.1));
+    let (ax, ay) = (f64::from(a.0), f64::from(a.1));
+    let (dx, dy) = (f64::from(b.0) - ax, f64::from(b.1) - ay);
+    let length = dx.mul_add(dx, dy * dy);
+    let t = if length == 0. {
+        0.
+    } else {
+        ((px - ax).mul_add(dx, (py - ay) * dy) / length).clamp(0., 1.)
+    };
+    libm::hypot(px - t.mul_add(dx, ax), py - t.mul_add(dy, ay))
+}
+
+/// Distance between the segments `[a, b]` and `[c, d]`, assuming they do not intersect
+fn segments_distance(a: (i32, i32), b: (i32, i32), c: (i32, i32), d: (i32, i32)) -> f64 {
+    point_segment_distance(a, c, d)
+        .min(point_segment_distance(b, c, d))
+        .min(point_segment_distance(c, a, b))
+        .min(point_segment_distance(d, a, b))
+}
+#[cfg(test)]
+mod tests {
+    use super::{Corner, Footprint, Side};
+
+    #[test]
+    fn test_rasterisation() {
+        let rectangle = Footprint::rectangle(0, 0, 10, 6);
+        assert_eq!(rectangle.cells().len(), 11 * 7);
+        assert_eq!(rectangle.boundary_cells().len(), 2 * 11 + 2 * 5);
+        assert_eq!(rectangle.area(), 60);
+
+        let l_shape = Footprint::l_shape(0, 0, 10, 10, (4, 4), Corner::NorthEast);
+        assert_eq!(l_shape.area(), 100 - 16);
+        assert!(!l_shape.contains((9, 1)));
+        assert!(l_shape.contains((6, 4)));
+
+        let t_shape = Footprint::t_shape(0, 0, 10, 10, 3, 4, Side::West);
+        assert_eq!(t_shape.area(), 30 + 7 * 4);
+        assert!(t_shape.contains((2, 0)));
+        assert!(!t_shape.contains((5, 0)));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let l_shape = Footprint::l_shape(0, 0, 10, 10, (5, 5), Corner::NorthEast);
+        // Fits in the cut corner
+        let small = Footprint::rectangle(7, 1, 2, 2);
+        assert!(!l_shape.overlaps(&small, 0));
+        assert!(l_shape.overlaps(&small, 3));
+
+        let rotated = Footprint::rotated_rectangle((5., 5.), 20, 2, 0.7);
+        assert!(l_shape.overlaps(&rotated, 0));
+        assert!(!rotated.overlaps(&Footprint::rectangle(30, 30, 5, 5), 8));
+    }
+}
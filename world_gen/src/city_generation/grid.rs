@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use super::CellType;
+
+/// Minimum number of cells added on a side when the grid grows
+const GROWTH_MARGIN: i64 = 256;
+/// Side of the chunks of the building index
+const CHUNK_SIZE: i32 = 64;
+
+/// Dense grid of the occupied cells, growing to fit every cell inserted
+///
+/// The bounds are kept in `i64` so that cells far apart don't overflow the index.
+#[derive(Clone, Debug, Default)]
+pub(super) struct OccupancyGrid {
+    /// Coordinates of the first cell
+    min: (i64, i64),
+    width: i64,
+    height: i64,
+    cells: Vec<Option<CellType>>,
+}
+
+impl OccupancyGrid {
+    fn index(&self, (x, y): (i32, i32)) -> Option<usize> {
+        let (dx, dy) = (i64::from(x) - self.min.0, i64::from(y) - self.min.1);
+        if dx < 0 || dy < 0 || dx >= self.width || dy >= self.height {
+            return None;
+        }
+        // Lower than the number of cells, so it fits
+        usize::try_from(dy * self.width + dx).ok()
+    }
+
+    pub(super) fn get(&self, pos: &(i32, i32)) -> Option<&CellType> {
+        self.index(*pos).and_then(|i| self.cells[i].as_ref())
+    }
+
+    pub(super) fn contains_key(&self, pos: &(i32, i32)) -> bool {
+        self.get(pos).is_some()
+    }
+
+    pub(super) fn insert(&mut self, pos: (i32, i32), cell: CellType) {
+        let i = match self.index(pos) {
+            Some(i) => i,
+            None => {
+                self.grow(pos);
+                self.index(pos).unwrap()
+            }
+        };
+        self.cells[i] = Some(cell);
+    }
+
+    pub(super) fn remove(&mut self, pos: &(i32, i32)) {
+        if let Some(i) = self.index(*pos) {
+            self.cells[i] = None;
+        }
+    }
+
+    /// Free every cell, the memory is kept
+    pub(super) fn clear(&mut self) {
+        self.cells.fill(None);
+    }
+
+    /// Make room for `pos`, growing at least by `GROWTH_MARGIN` or by the current size
+    fn grow(&mut self, pos: (i32, i32)) {
+        let (x, y) = (i64::from(pos.0), i64::from(pos.1));
+        let (max_x, max_y) = (self.min.0 + self.width, self.min.1 + self.height);
+        let margin_x = GROWTH_MARGIN.max(self.width);
+        let margin_y = GROWTH_MARGIN.max(self.height);
+        let (new_min_x, new_max_x) = if self.cells.is_empty() {
+            (x - GROWTH_MARGIN, x + GROWTH_MARGIN)
+        } else if x < self.min.0 {
+            (x - margin_x, max_x)
+        } else if x >= max_x {
+            (self.min.0, x + margin_x)
+        } else {
+            (self.min.0, max_x)
+        };
+        let (new_min_y, new_max_y) = if self.cells.is_empty() {
+            (y - GROWTH_MARGIN, y + GROWTH_MARGIN)
+        } else if y < self.min.1 {
+            (y - margin_y, max_y)
+        } else if y >= max_y {
+            (self.min.1, y + margin_y)
+        } else {
+            (self.min.1, max_y)
+        };
+
+        let (width, height) = (new_max_x - new_min_x, new_max_y - new_min_y);
+        let offset = |n: i64| usize::try_from(n).expect("offsets in the grid are positive");
+        let len = width
+            .checked_mul(height)
+            .and_then(|len| usize::try_from(len).ok())
+            .expect("the grid is too large");
+        let mut cells = vec![None; len];
+        let old_width = offset(self.width);
+        for row in 0..self.height {
+            let from = offset(row * self.width);
+            let to = offset((row + self.min.1 - new_min_y) * width + self.min.0 - new_min_x);
+            cells[to..to + old_width].copy_from_slice(&self.cells[from..from + old_width]);
+        }
+        *self = Self {
+            min: (new_min_x, new_min_y),
+            width,
+            height,
+            cells,
+        };
+    }
+}
+
+/// Buildings bucketed by the chunks their bounding box covers, to only check the close ones
+#[derive(Clone, Debug, Default)]
+pub(super) struct BuildingIndex {
+    chunks: HashMap<(i32, i32), Vec<(i32, i32)>>,
+    /// Keys of the buildings in insertion order
+    keys: Vec<(i32, i32)>,
+}
+
+impl BuildingIndex {
+    /// Register the building stored at `key` with its bounding box
+    pub(super) fn insert(&mut self, key: (i32, i32), bounding_box: (i32, i32, i32, i32)) {
+        for chunk in chunks(bounding_box) {
+            self.chunks.entry(chunk).or_default().push(key);
+        }
+        self.keys.push(key);
+    }
+
+    /// Keys of the buildings whose bounding box may intersect `bounding_box`, sorted
+    pub(super) fn query(&self, bounding_box: (i32, i32, i32, i32)) -> Vec<(i32, i32)> {
+        let mut keys = chunks(bounding_box)
+            .filter_map(|chunk| self.chunks.get(&chunk))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    /// Keys of every building, in insertion order
+    pub(super) fn keys(&self) -> &[(i32, i32)] {
+        &self.keys
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.chunks.clear();
+        self.keys.clear();
+    }
+}
+
+/// Chunks covered by a bounding box
+fn chunks((min_x, min_y, max_x, max_y): (i32, i32, i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    let (x0, x1) = (min_x.div_euclid(CHUNK_SIZE), max_x.div_euclid(CHUNK_SIZE));
+    let (y0, y1) = (min_y.div_euclid(CHUNK_SIZE), max_y.div_euclid(CHUNK_SIZE));
+    (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildingIndex, CellType, OccupancyGrid};
+
+    #[test]
+    fn test_occupancy_grid() {
+        let mut grid = OccupancyGrid::default();
+        assert!(!grid.contains_key(&(0, 0)));
+        grid.insert((3, -4), CellType::Road);
+        // Growing in every direction keeps the cells
+        grid.insert((-1000, 5), CellType::Building);
+        grid.insert((700, 2000), CellType::Wall);
+        grid.insert((10, -3000), CellType::Road);
+        assert!(matches!(grid.get(&(3, -4)), Some(CellType::Road)));
+        assert!(matches!(grid.get(&(-1000, 5)), Some(CellType::Building)));
+        assert!(matches!(grid.get(&(700, 2000)), Some(CellType::Wall)));
+        assert!(matches!(grid.get(&(10, -3000)), Some(CellType::Road)));
+        assert!(!grid.contains_key(&(3, -3)));
+        // Far away cells don't overflow the index
+        assert!(!grid.contains_key(&(i32::MAX, i32::MIN)));
+
+        grid.remove(&(3, -4));
+        assert!(!grid.contains_key(&(3, -4)));
+        grid.clear();
+        assert!(!grid.contains_key(&(-1000, 5)));
+    }
+
+    #[test]
+    fn test_building_index() {
+        let mut index = BuildingIndex::default();
+        index.insert((0, 0), (0, 0, 10, 10));
+        index.insert((200, 200), (200, 200, 230, 210));
+        index.insert((-100, 50), (-100, 50, -60, 80));
+        assert_eq!(index.query((5, 5, 20, 20)), vec![(0, 0)]);
+        assert_eq!(index.query((-70, 0, 5, 60)), vec![(-100, 50), (0, 0)]);
+        assert!(index.query((500, 500, 520, 520)).is_empty());
+        assert_eq!(index.keys(), &[(0, 0), (200, 200), (-100, 50)]);
+    }
+}
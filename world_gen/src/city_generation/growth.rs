@@ -0,0 +1,227 @@
+use rand::{seq::SliceRandom, Rng};
+
+use super::{Building, CityGenError, CityGenerator};
+
+/// New buildings per existing building and per year
+const GROWTH_RATE: f64 = 0.03;
+/// Attempts to place a building before giving up for the year
+const MAX_PLACEMENT_ATTEMPTS: usize = 50;
+/// Distance between a road and the buildings built along it
+const ROAD_SETBACK: i32 = 3;
+
+/// Where a new building is placed
+#[derive(Clone, Copy, Debug)]
+enum GrowthKind {
+    /// Along an existing road
+    Road,
+    /// In the gap between a building and its closest neighbour
+    Infill,
+    /// Around an important building
+    Densification,
+}
+
+impl CityGenerator {
+    /// Grow an already generated city for some years, returns the number of buildings added
+    ///
+    /// Every year, new buildings are built along the roads, between existing buildings and around
+    /// important buildings, the city can be cloned between steps to keep every stage. Once the
+    /// city has a wall, it only grows inside of it and the wall stays as it is.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the city wasn't generated or if no building could be added during a year, the
+    /// years before it are kept.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn step(&mut self, years: u32) -> Result<usize, CityGenError> {
+        if self.buildings.is_empty() {
+            return Err(CityGenError::NoBuildings);
+        }
+        if self.important_buildings.is_empty() {
+            return Err(CityGenError::NoImportantBuildings);
+        }
+        let (mut added, mut requested) = (0, 0);
+        for _ in 0..years {
+            let new_buildings =
+                ((self.buildings.len() as f64 * GROWTH_RATE).round() as usize).max(1);
+            requested += new_buildings;
+            let added_before = added;
+            for _ in 0..new_buildings {
+                for _ in 0..MAX_PLACEMENT_ATTEMPTS {
+                    let kind = match self.rng.gen_range(0..10) {
+                        0..5 => GrowthKind::Road,
+                        5..8 => GrowthKind::Infill,
+                        _ => GrowthKind::Densification,
+                    };
+                    let width = self.rng.gen_range(self.config.width_bound.clone());
+                    let height = self.rng.gen_range(self.config.height_bound.clone());
+                    let Some(spawn) = self.growth_spawn(kind, width, height) else {
+                        continue;
+                    };
+                    if let Some(id) = self.try_place_building(spawn, width, height) {
+                        let building_type = Self::random_building_type(&mut self.rng);
+                        if let Some(building) = self.buildings.values_mut().find(|b| b.id == id) {
+                            building.building_type = building_type;
+                        }
+                        added += 1;
+                        break;
+                    }
+                }
+            }
+            self.age += 1;
+            if added == added_before {
+                self.update_borders();
+                return Err(CityGenError::PlacementFailed {
+                    placed: added,
+                    requested,
+                });
+            }
+        }
+        self.update_borders();
+        Ok(added)
+    }
+
+    /// Top left corner of a new building
+    fn growth_spawn(&mut self, kind: GrowthKind, width: i32, height: i32) -> Option<(i32, i32)> {
+        match kind {
+            GrowthKind::Road => {
+                let road = self
+                    .roads
+                    .iter()
+                    .filter(|road| road.len() > 2)
+                    .collect::<Vec<_>>()
+                    .choose(&mut self.rng)
+                    .copied()?;
+                let i = self.rng.gen_range(1..road.len() - 1);
+                let (x, y) = road[i];
+                // The building is on one side of the road, facing it
+                let side = if self.rng.gen_bool(0.5) { 1 } else { -1 };
+                let along_x = road[i + 1].0 != road[i - 1].0;
+                Some(if along_x {
+                    let y = if side > 0 {
+                        y + ROAD_SETBACK
+                    } else {
+                        y - ROAD_SETBACK - height
+                    };
+                    (x - width / 2, y)
+                } else {
+                    let x = if side > 0 {
+                        x + ROAD_SETBACK
+                    } else {
+                        x - ROAD_SETBACK - width
+                    };
+                    (x, y - height / 2)
+                })
+            }
+            GrowthKind::Infill => {
+                let building = self.random_building(false)?;
+                let center = |b: &Building| (b.x + b.width / 2, b.y + b.height / 2);
+                let (x, y) = center(&building);
+                let (nx, ny) = self
+                    .buildings
+                    .values()
+                    .filter(|b| b.id != building.id)
+                    .map(center)
+                    .min_by_key(|(nx, ny)| ((nx - x).abs() + (ny - y).abs(), *nx, *ny))?;
+                // Centered on the middle of the gap
+                Some(((x + nx) / 2 - width / 2, (y + ny) / 2 - height / 2))
+            }
+            GrowthKind::Densification => {
+                let building = self.random_building(true)?;
+                let distance = self.rng.gen_range(self.config.distance_bound.clone());
+                let angle = self.rng.gen_range(0.0..std::f64::consts::TAU);
+                let (sin, cos) = libm::sincos(angle);
+                let radius = f64::from(distance + building.width.max(building.height) / 2);
+                #[allow(clippy::cast_possible_truncation)]
+                Some((
+                    building.x + building.width / 2 + (cos * radius) as i32 - width / 2,
+                    building.y + building.height / 2 + (sin * radius) as i32 - height / 2,
+                ))
+            }
+        }
+    }
+
+    /// Pick a random building, sorted first so that the choice only depends on the seed
+    fn random_building(&mut self, important: bool) -> Option<Building> {
+        let mut buildings = self
+            .buildings
+            .values()
+            .filter(|b| !important || b.is_important)
+            .collect::<Vec<_>>();
+        buildings.sort_by_key(|b| b.id);
+        buildings.choose(&mut self.rng).map(|b| (*b).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::city_generation::{CityConfig, CityGenError, CityGenerator, Footprint};
+
+    fn city() -> CityGenerator {
+        let mut city_gen = CityGenerator::new(
+            CityConfig::default()
+                .set_seed(4)
+                .set_buildings(60)
+                .set_important_buildings(3)
+                .set_width_bound(10..20)
+                .set_height_bound(10..20),
+        );
+        city_gen.generate().unwrap();
+        city_gen
+    }
+
+    #[test]
+    fn test_step() {
+        let mut city_gen = city();
+        let mut count = city_gen.buildings.len();
+        let age = city_gen.age;
+        for years in [1, 3, 5] {
+            let before = city_gen.age;
+            let added = city_gen.step(years).unwrap();
+            assert_eq!(city_gen.age, before + years);
+            assert!(added > 0);
+            assert_eq!(city_gen.buildings.len(), count + added);
+            count = city_gen.buildings.len();
+        }
+        assert_eq!(city_gen.age, age + 9);
+        assert!(count > 60);
+
+        let mut other = city();
+        other.step(9).unwrap();
+        assert_eq!(other.buildings, city_gen.buildings);
+    }
+
+    #[test]
+    fn test_step_walled() {
+        let mut city_gen = city();
+        city_gen.generate_wall(10);
+        let wall = city_gen.wall.clone().unwrap();
+        let count = city_gen.buildings.len();
+        let added = city_gen.step(20).unwrap();
+        assert!(added > 0);
+        assert_eq!(city_gen.buildings.len(), count + added);
+
+        // The wall is kept and every building is inside of it
+        assert_eq!(city_gen.wall, Some(wall.clone()));
+        let area = Footprint {
+            vertices: wall.outline,
+        };
+        for building in city_gen.buildings.values() {
+            assert!(building
+                .footprint
+                .cells()
+                .iter()
+                .all(|pos| area.contains(*pos)));
+        }
+    }
+
+    #[test]
+    fn test_step_not_generated() {
+        let mut city_gen = CityGenerator::new(CityConfig::default());
+        assert_eq!(city_gen.step(1), Err(CityGenError::NoBuildings));
+        assert_eq!(city_gen.age, 0);
+    }
+}
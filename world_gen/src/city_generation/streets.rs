@@ -0,0 +1,362 @@
+use clap::ValueEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+    f64::consts::FRAC_PI_2,
+};
+
+use super::{line_cells, neighbours, Building, CellType, CityGenerator, Footprint};
+
+/// Length of a segment of a primary street
+const PRIMARY_SEGMENT_LENGTH: f64 = 48.;
+/// Length of a segment of a secondary street
+const SECONDARY_SEGMENT_LENGTH: f64 = 36.;
+/// Maximum deviation of an organic street at each segment, in radians
+const MAX_DEVIATION: f64 = 0.35;
+/// Segment ends closer than this to an intersection are snapped to it
+const SNAP_DISTANCE: i32 = 8;
+/// Minimum distance between two streets that don't cross
+const MIN_STREET_SPACING: i32 = 12;
+/// Distance between a street and the buildings lining it
+const LOT_SETBACK: i32 = 2;
+/// Distance between two lot candidates along a street
+const LOT_STEP: usize = 4;
+/// Street length grown for every building that has to be placed, in largest building sides
+const FRONTAGE_PER_BUILDING: f64 = 1.5;
+
+/// How the city is laid out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutMode {
+    /// Buildings are placed first, then connected by roads
+    #[default]
+    BuildingFirst,
+    /// Streets are grown first, then lined by buildings facing them
+    StreetFirst,
+}
+
+/// Street segment waiting to be built
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Segment {
+    start: (i32, i32),
+    angle: f64,
+    primary: bool,
+}
+
+impl CityGenerator {
+    /// Grow the street network from the center of the city, then line the streets with lots
+    ///
+    /// The first lots, closest to the center, are given to the important buildings. Lots are
+    /// either lined along the streets or subdivided from the blocks. Returns the number of
+    /// buildings placed, lower than requested if the lots ran out.
+    pub(super) fn generate_street_first(
+        &mut self,
+        normal_buildings: usize,
+        important_buildings: usize,
+    ) -> usize {
+        self.generate_streets(normal_buildings + important_buildings);
+        let (mut normal_buildings, mut important_buildings) =
+            (normal_buildings, important_buildings);
+        let mut placed = 0;
+        if self.config.subdivide_blocks {
+            placed = self.generate_block_buildings(normal_buildings, important_buildings);
+            // What doesn't fit in the blocks is lined along the streets
+            normal_buildings -= placed.saturating_sub(important_buildings);
+            important_buildings = important_buildings.saturating_sub(placed);
+        }
+        placed += self.generate_lots(normal_buildings, important_buildings);
+        self.update_borders();
+        // Streets can go further than the buildings
+        for (x, y) in self.roads.iter().flatten() {
+            self.min_x = self.min_x.min(*x - 1);
+            self.min_y = self.min_y.min(*y - 1);
+            self.max_x = self.max_x.max(*x + 1);
+            self.max_y = self.max_y.max(*y + 1);
+        }
+        placed
+    }
+
+    /// Grow primary and secondary streets, closest segments to the center first
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    pub(super) fn generate_streets(&mut self, buildings: usize) {
+        let max_radius = i64::from(self.config.important_buildings_max_distance / 2).pow(2);
+        let largest_side = self
+            .config
+            .width_bound
+            .end
+            .max(self.config.height_bound.end);
+        let wanted_length = buildings as f64 * FRONTAGE_PER_BUILDING * f64::from(largest_side);
+        let base_angle = self.rng.gen_range(0.0..FRAC_PI_2) * (1. - self.config.grid_bias);
+
+        let mut primary = vec![];
+        let mut secondary = vec![];
+        let mut intersections = vec![(0, 0)];
+        let mut length = 0.;
+        let mut queue = BinaryHeap::new();
+        let mut segments = vec![];
+        for i in 0..4 {
+            push_segment(
+                &mut queue,
+                &mut segments,
+                Segment {
+                    start: (0, 0),
+                    angle: base_angle + f64::from(i) * FRAC_PI_2,
+                    primary: true,
+                },
+            );
+        }
+
+        while let Some(Reverse((distance, index))) = queue.pop() {
+            if length >= wanted_length {
+                break;
+            }
+            if distance > max_radius {
+                continue;
+            }
+            let segment = segments[index];
+            let Some((cells, junction)) = self.street_segment(&segment, &intersections) else {
+                continue;
+            };
+            let end = *cells.last().unwrap();
+            length += cells.len() as f64;
+            for pos in &cells {
+                self.is_something.insert(*pos, CellType::Road);
+            }
+            intersections.push(end);
+            if segment.primary {
+                primary.push(cells);
+            } else {
+                secondary.push(cells);
+            }
+            if junction {
+                continue;
+            }
+
+            // The street goes on, bending unless it follows the grid
+            let forward = self.street_angle(segment.angle, base_angle);
+            push_segment(
+                &mut queue,
+                &mut segments,
+                Segment {
+                    start: end,
+                    angle: forward,
+                    primary: segment.primary,
+                },
+            );
+            // Side streets, more frequent in a grid
+            let branch_probability = 0.3 + 0.5 * self.config.grid_bias;
+            for side in [-1., 1.] {
+                if self.rng.gen_bool(branch_probability) {
+                    let angle = self.street_angle(segment.angle + side * FRAC_PI_2, base_angle);
+                    push_segment(
+                        &mut queue,
+                        &mut segments,
+                        Segment {
+                            start: end,
+                            angle,
+                            primary: false,
+                        },
+                    );
+                }
+            }
+        }
+
+        self.main_roads = primary.len();
+        self.roads = primary.into_iter().chain(secondary).collect();
+    }
+
+    /// Direction of a new segment, snapped to the grid depending on `grid_bias`
+    fn street_angle(&mut self, angle: f64, base_angle: f64) -> f64 {
+        let grid_angle = base_angle + ((angle - base_angle) / FRAC_PI_2).round() * FRAC_PI_2;
+        let deviation =
+            self.rng.gen_range(-MAX_DEVIATION..MAX_DEVIATION) * (1. - self.config.grid_bias);
+        (grid_angle - angle).mul_add(self.config.grid_bias, angle) + deviation
+    }
+
+    /// Cells of a segment once the local constraints are applied and if it ends on a junction
+    ///
+    /// The segment stops on the first street it crosses and ends close to an intersection are
+    /// snapped to it. Segments running too close to another street are dropped.
+    #[allow(clippy::cast_possible_truncation)]
+    fn street_segment(
+        &self,
+        segment: &Segment,
+        intersections: &[(i32, i32)],
+    ) -> Option<(Vec<(i32, i32)>, bool)> {
+        let length = if segment.primary {
+            PRIMARY_SEGMENT_LENGTH
+        } else {
+            SECONDARY_SEGMENT_LENGTH
+        };
+        let (sin, cos) = libm::sincos(segment.angle);
+        let (x, y) = segment.start;
+        let mut end = (
+            x + (cos * length).round() as i32,
+            y + (sin * length).round() as i32,
+        );
+        let mut junction = false;
+        if let Some(intersection) = intersections.iter().find(|(ix, iy)| {
+            (ix - end.0).abs() + (iy - end.1).abs() <= SNAP_DISTANCE && (*ix, *iy) != segment.start
+        }) {
+            end = *intersection;
+            junction = true;
+        }
+
+        let is_road = |p: &(i32, i32)| matches!(self.is_something.get(p), Some(CellType::Road));
+        let mut cells = vec![];
+        for pos in line_cells(segment.start, end) {
+            let leaving_start = (pos.0 - x).abs() + (pos.1 - y).abs() <= 1;
+            if cells.is_empty() || leaving_start || !is_road(&pos) {
+                cells.push(pos);
+                continue;
+            }
+            // Crossing another street, stop there
+            cells.push(pos);
+            junction = true;
+            break;
+        }
+        if cells.len() < 3 {
+            return None;
+        }
+
+        // The segment must not run along another street
+        let own = cells.iter().copied().collect::<HashSet<_>>();
+        let too_close = cells
+            .iter()
+            .skip(MIN_STREET_SPACING as usize)
+            .take(cells.len().saturating_sub(2 * MIN_STREET_SPACING as usize))
+            .any(|(cx, cy)| {
+                (-MIN_STREET_SPACING / 2..=MIN_STREET_SPACING / 2).any(|i| {
+                    (-MIN_STREET_SPACING / 2..=MIN_STREET_SPACING / 2).any(|j| {
+                        let p = (cx + i, cy + j);
+                        !own.contains(&p) && is_road(&p)
+                    })
+                })
+            });
+        if too_close {
+            None
+        } else {
+            Some((cells, junction))
+        }
+    }
+
+    /// Place buildings on both sides of the streets, closest to the center first
+    ///
+    /// Returns the number of buildings placed.
+    #[allow(clippy::cast_possible_truncation)]
+    fn generate_lots(&mut self, normal_buildings: usize, important_buildings: usize) -> usize {
+        let mut candidates = vec![];
+        for road in &self.roads {
+            let (first, last) = (road[0], road[road.len() - 1]);
+            let angle = libm::atan2(f64::from(last.1 - first.1), f64::from(last.0 - first.0));
+            for pos in road.iter().skip(LOT_STEP / 2).step_by(LOT_STEP) {
+                for side in [-1., 1.] {
+                    candidates.push((*pos, angle, side));
+                }
+            }
+        }
+        candidates.sort_by_key(|((x, y), _, _)| i64::from(*x).pow(2) + i64::from(*y).pow(2));
+
+        let mut placed = 0;
+        for (pos, angle, side) in candidates {
+            if placed == normal_buildings + important_buildings {
+                break;
+            }
+            let important = placed < important_buildings;
+            let (width, depth) = if important {
+                (
+                    self.config.width_bound.end - 1,
+                    self.config.height_bound.end - 1,
+                )
+            } else {
+                (
+                    self.rng.gen_range(self.config.width_bound.clone()),
+                    self.rng.gen_range(self.config.height_bound.clone()),
+                )
+            };
+            let (sin, cos) = libm::sincos(angle);
+            // Normal to the street, on the chosen side
+            let (nx, ny) = (-sin * side, cos * side);
+            let distance = f64::from(LOT_SETBACK) + f64::from(depth) / 2. + 1.;
+            let center = (
+                f64::from(pos.0) + nx * distance,
+                f64::from(pos.1) + ny * distance,
+            );
+            let footprint = Footprint::rotated_rectangle(center, width, depth, angle);
+            if let Some(building) = self.lot_building(footprint, pos) {
+                self.add_lot_building(building, important);
+                placed += 1;
+            }
+        }
+        placed
+    }
+
+    /// Keep track of a building built on a lot
+    pub(super) fn add_lot_building(&mut self, building: Building, important: bool) {
+        let building = if important {
+            self.important_buildings.push((building.x, building.y));
+            building.make_important()
+        } else {
+            building
+        };
+        self.insert_building(building);
+    }
+
+    /// Building on a lot facing the street at `street`, if the lot is free
+    ///
+    /// The door is the closest one to the street, a short path links them.
+    pub(super) fn lot_building(
+        &mut self,
+        footprint: Footprint,
+        street: (i32, i32),
+    ) -> Option<Building> {
+        let cells = footprint.cells();
+        let free = |p: &(i32, i32)| !self.is_something.contains_key(p);
+        // Keep a gap of one cell around every building
+        if !cells
+            .iter()
+            .all(|pos| free(pos) && neighbours(*pos).iter().all(free))
+        {
+            return None;
+        }
+        let door = *footprint
+            .door_candidates()
+            .iter()
+            .min_by_key(|(x, y)| (x - street.0).pow(2) + (y - street.1).pow(2))?;
+        // Door candidates have a single neighbour outside of the building
+        let outside = neighbours(door)
+            .into_iter()
+            .find(|pos| !footprint.contains(*pos))?;
+        let path = line_cells(outside, street);
+        if path
+            .iter()
+            .any(|pos| matches!(self.is_something.get(pos), Some(CellType::Building)))
+        {
+            return None;
+        }
+
+        let building = Building::with_doors(footprint, vec![door], self.buildings.len());
+        self.register_building(&building);
+        for pos in &path {
+            self.is_something.insert(*pos, CellType::Road);
+        }
+        if !path.is_empty() {
+            self.roads.push(path);
+        }
+        Some(building)
+    }
+}
+
+/// Queue a segment, ordered by distance of its start to the center then by creation
+fn push_segment(
+    queue: &mut BinaryHeap<Reverse<(i64, usize)>>,
+    segments: &mut Vec<Segment>,
+    segment: Segment,
+) {
+    let (x, y) = segment.start;
+    let distance = i64::from(x).pow(2) + i64::from(y).pow(2);
+    queue.push(Reverse((distance, segments.len())));
+    segments.push(segment);
+}
@@ -0,0 +1,358 @@
+use pathfinding::prelude::{astar, dijkstra};
+use std::collections::HashSet;
+
+use super::{line_cells, neighbours, CellType, CityGenerator, Footprint, CITY_BOUNDS_OFFSET};
+
+/// Maximum length of wall between two towers
+const TOWER_SPACING: i32 = 120;
+/// Gates closer than this are merged
+const GATE_SPACING: i32 = 60;
+
+/// Fortifications around the city
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CityWall {
+    /// Vertices of the wall, the last one is connected to the first one
+    pub outline: Vec<(i32, i32)>,
+    /// Cells of the wall, gates excluded
+    pub cells: Vec<(i32, i32)>,
+    /// Towers, on the corners of the wall and along its longest sides
+    pub towers: Vec<(i32, i32)>,
+    /// Openings in the wall, roads go through them
+    pub gates: Vec<(i32, i32)>,
+}
+
+impl CityGenerator {
+    /// Surround the generated city with a wall `padding` cells away from the buildings
+    ///
+    /// Main roads crossing the wall get a gate, there is also a gate in every cardinal direction
+    /// leading to a road going out of the city. Other roads are rerouted through the gates, those
+    /// that can't be get a gate of their own where they cross the wall.
+    pub fn generate_wall(&mut self, padding: i32) {
+        let points = self
+            .buildings
+            .values()
+            .flat_map(|b| b.footprint.vertices.iter().copied())
+            .collect::<Vec<_>>();
+        let hull = convex_hull(points);
+        if hull.len() < 3 {
+            return;
+        }
+        let outline = offset_polygon(&hull, padding);
+        let center = centroid(&outline);
+        let area = Footprint {
+            vertices: outline.clone(),
+        };
+
+        let mut wall_cells = vec![];
+        for (from, to) in outline.iter().zip(outline.iter().cycle().skip(1)) {
+            let mut line = line_cells(*from, *to);
+            line.pop();
+            wall_cells.extend(line);
+        }
+        let wall_set = wall_cells.iter().copied().collect::<HashSet<_>>();
+
+        // Main roads keep their crossing, a gate is built there
+        let mut gates: Vec<(i32, i32)> = vec![];
+        for road in &self.roads[..self.main_roads] {
+            if let Some(crossing) = road.iter().find(|pos| wall_set.contains(pos)) {
+                add_gate(&mut gates, *crossing);
+            }
+        }
+        // Gates facing every direction
+        for direction in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            if let Some(crossing) = (0..)
+                .map(|i| (center.0 + direction.0 * i, center.1 + direction.1 * i))
+                .take_while(|pos| area.contains(*pos))
+                .last()
+                .and_then(|last| {
+                    (0..=2)
+                        .map(|i| (last.0 + direction.0 * i, last.1 + direction.1 * i))
+                        .find(|pos| wall_set.contains(pos))
+                })
+            {
+                add_gate(&mut gates, crossing);
+            }
+        }
+
+        for pos in &wall_cells {
+            self.is_something.insert(*pos, CellType::Wall);
+        }
+        for gate in &gates {
+            self.is_something.insert(*gate, CellType::Road);
+        }
+        let (min_x, min_y, max_x, max_y) = area.bounding_box();
+        self.min_x = self.min_x.min(min_x - padding - CITY_BOUNDS_OFFSET);
+        self.min_y = self.min_y.min(min_y - padding - CITY_BOUNDS_OFFSET);
+        self.max_x = self.max_x.max(max_x + padding + CITY_BOUNDS_OFFSET);
+        self.max_y = self.max_y.max(max_y + padding + CITY_BOUNDS_OFFSET);
+
+        self.reroute_roads_through_gates(&wall_set, &gates);
+        for gate in gates.clone() {
+            self.connect_gate(gate, center, &area, padding);
+        }
+        // Roads kept as they were still cross the wall, they already lead somewhere on both sides
+        let crossings = self
+            .roads
+            .iter()
+            .flatten()
+            .filter(|pos| wall_set.contains(pos))
+            .copied()
+            .collect::<Vec<_>>();
+        for crossing in crossings {
+            if !gates.contains(&crossing) {
+                self.is_something.insert(crossing, CellType::Road);
+                gates.push(crossing);
+            }
+        }
+
+        let towers = towers(&outline);
+        self.wall = Some(CityWall {
+            outline,
+            cells: wall_cells
+                .into_iter()
+                .filter(|pos| !gates.contains(pos))
+                .collect(),
+            towers,
+            gates,
+        });
+    }
+
+    /// Roads crossing the wall outside of a gate are computed again, those without a route through
+    /// a gate are kept as they were
+    fn reroute_roads_through_gates(&mut self, wall: &HashSet<(i32, i32)>, gates: &[(i32, i32)]) {
+        for i in 0..self.roads.len() {
+            let crosses = self.roads[i]
+                .iter()
+                .any(|pos| wall.contains(pos) && !gates.contains(pos));
+            if !crosses {
+                continue;
+            }
+            let road = std::mem::take(&mut self.roads[i]);
+            let other_roads = self.roads.iter().flatten().copied().collect::<HashSet<_>>();
+            for pos in &road {
+                if !other_roads.contains(pos)
+                    && matches!(self.is_something.get(pos), Some(CellType::Road))
+                {
+                    self.is_something.remove(pos);
+                }
+            }
+            let (Some(&start), Some(&end)) = (road.first(), road.last()) else {
+                continue;
+            };
+            let rerouted = astar(
+                &start,
+                |&p| self.successors(p),
+                |&(x, y)| ((x - end.0).abs() + (y - end.1).abs()) * 10,
+                |&p| p == end,
+            )
+            .map_or(road, |(path, _)| path);
+            for pos in rerouted.iter().filter(|pos| !wall.contains(pos)) {
+                self.is_something.insert(*pos, CellType::Road);
+            }
+            self.roads[i] = rerouted;
+        }
+    }
+
+    /// Link a gate to the closest road inside the city and add a road leaving the city
+    #[allow(clippy::cast_possible_truncation)]
+    fn connect_gate(
+        &mut self,
+        gate: (i32, i32),
+        center: (i32, i32),
+        area: &Footprint,
+        padding: i32,
+    ) {
+        let (dx, dy) = (f64::from(gate.0 - center.0), f64::from(gate.1 - center.1));
+        let length = libm::hypot(dx, dy).max(1.);
+        let outward = |distance: i32| {
+            (
+                gate.0 + (dx / length * f64::from(distance)).round() as i32,
+                gate.1 + (dy / length * f64::from(distance)).round() as i32,
+            )
+        };
+
+        // Inside, the first cell after the gate is the one closest to the center
+        let inside = neighbours(gate)
+            .into_iter()
+            .filter(|pos| area.contains(*pos) && !self.is_wall(*pos))
+            .min_by_key(|(x, y)| (x - center.0).abs() + (y - center.1).abs());
+        if let Some(inside) = inside {
+            let road = dijkstra(
+                &inside,
+                |&p| self.successors(p),
+                |&p| {
+                    p != inside
+                        && p != gate
+                        && area.contains(p)
+                        && matches!(self.is_something.get(&p), Some(CellType::Road))
+                },
+            )
+            .map(|(mut path, _)| {
+                path.insert(0, gate);
+                path
+            });
+            if let Some(road) = road {
+                for pos in &road {
+                    self.is_something.insert(*pos, CellType::Road);
+                }
+                self.roads.push(road);
+            }
+        }
+
+        // Outside, a straight road leaves the city
+        let road = line_cells(gate, outward(padding))
+            .into_iter()
+            .filter(|pos| *pos == gate || !self.is_wall(*pos))
+            .collect::<Vec<_>>();
+        for pos in &road {
+            self.is_something.insert(*pos, CellType::Road);
+        }
+        self.roads.push(road);
+    }
+
+    fn is_wall(&self, pos: (i32, i32)) -> bool {
+        matches!(self.is_something.get(&pos), Some(CellType::Wall))
+    }
+}
+
+/// Add a gate unless there is already one close to it
+fn add_gate(gates: &mut Vec<(i32, i32)>, pos: (i32, i32)) {
+    if gates
+        .iter()
+        .all(|gate| (gate.0 - pos.0).abs() + (gate.1 - pos.1).abs() > GATE_SPACING)
+    {
+        gates.push(pos);
+    }
+}
+
+/// Convex hull of the points (monotone chain), without collinear points
+fn convex_hull(mut points: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    points.sort_unstable();
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: (i32, i32), a: (i32, i32), b: (i32, i32)| {
+        i64::from(a.0 - o.0) * i64::from(b.1 - o.1) - i64::from(a.1 - o.1) * i64::from(b.0 - o.0)
+    };
+    let mut hull: Vec<(i32, i32)> = Vec::with_capacity(points.len() * 2);
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for p in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // Last point is the first one of the next pass
+        hull.pop();
+    }
+    hull
+}
+
+/// Move every side of a convex polygon `distance` away from its center
+#[allow(clippy::cast_possible_truncation)]
+fn offset_polygon(polygon: &[(i32, i32)], distance: i32) -> Vec<(i32, i32)> {
+    let center = centroid(polygon);
+    let (cx, cy) = (f64::from(center.0), f64::from(center.1));
+    let normal = |a: (i32, i32), b: (i32, i32)| {
+        let (dx, dy) = (f64::from(b.0 - a.0), f64::from(b.1 - a.1));
+        let length = libm::hypot(dx, dy);
+        let (nx, ny) = (dy / length, -dx / length);
+        // Pointing outwards
+        if nx * (f64::from(a.0) - cx) + ny * (f64::from(a.1) - cy) < 0. {
+            (-nx, -ny)
+        } else {
+            (nx, ny)
+        }
+    };
+    let n = polygon.len();
+    (0..n)
+        .map(|i| {
+            let (prev, current, next) =
+                (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+            let (n1, n2) = (normal(prev, current), normal(current, next));
+            // Limit the spike on sharp corners
+            let factor = f64::from(distance) / (1. + n1.0 * n2.0 + n1.1 * n2.1).max(0.5);
+            (
+                current.0 + ((n1.0 + n2.0) * factor).round() as i32,
+                current.1 + ((n1.1 + n2.1) * factor).round() as i32,
+            )
+        })
+        .collect()
+}
+
+/// Average of the vertices of a polygon
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn centroid(polygon: &[(i32, i32)]) -> (i32, i32) {
+    let n = polygon.len().max(1) as i64;
+    let (sx, sy) = polygon.iter().fold((0i64, 0i64), |(sx, sy), (x, y)| {
+        (sx + i64::from(*x), sy + i64::from(*y))
+    });
+    ((sx / n) as i32, (sy / n) as i32)
+}
+
+/// Towers on every corner and along the sides so that they are at most `TOWER_SPACING` apart
+#[allow(clippy::cast_possible_truncation)]
+fn towers(outline: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let mut towers = vec![];
+    for (from, to) in outline.iter().zip(outline.iter().cycle().skip(1)) {
+        towers.push(*from);
+        let (dx, dy) = (f64::from(to.0 - from.0), f64::from(to.1 - from.1));
+        let segments = (libm::hypot(dx, dy) / f64::from(TOWER_SPACING)).ceil() as i32;
+        for i in 1..segments {
+            let t = f64::from(i) / f64::from(segments);
+            towers.push((
+                from.0 + (dx * t).round() as i32,
+                from.1 + (dy * t).round() as i32,
+            ));
+        }
+    }
+    towers
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::city_generation::{CityConfig, CityGenerator, Footprint};
+
+    #[test]
+    fn test_wall() {
+        // With a small padding, some roads can't be rerouted through the planned gates
+        for (seed, padding) in [(3, 10), (1, 2)] {
+            let mut city_gen = CityGenerator::new(
+                CityConfig::default()
+                    .set_seed(seed)
+                    .set_buildings(120)
+                    .set_important_buildings(4)
+                    .set_width_bound(10..30)
+                    .set_height_bound(10..30),
+            );
+            city_gen.generate().unwrap();
+            city_gen.generate_wall(padding);
+            let wall = city_gen.wall.clone().unwrap();
+            assert!(!wall.gates.is_empty());
+
+            let cells = wall.cells.iter().copied().collect::<HashSet<_>>();
+            assert!(wall.gates.iter().all(|gate| !cells.contains(gate)));
+            // Every road crossing the wall goes through a gate
+            for road in &city_gen.roads {
+                assert!(road.iter().all(|pos| !cells.contains(pos)));
+            }
+
+            let area = Footprint {
+                vertices: wall.outline.clone(),
+            };
+            for building in city_gen.buildings.values() {
+                assert!(building
+                    .footprint
+                    .cells()
+                    .iter()
+                    .all(|pos| area.contains(*pos)));
+            }
+        }
+    }
+}
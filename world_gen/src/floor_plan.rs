@@ -0,0 +1,603 @@
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::city_generation::{neighbours, BuildingType, Footprint};
+
+/// Rooms smaller than this are merged with a neighbour
+const MIN_ROOM_CELLS: usize = 4;
+
+/// What a room is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum RoomPurpose {
+    Hall,
+    LivingRoom,
+    Kitchen,
+    Bedroom,
+    Storage,
+    Workroom,
+    ShopFloor,
+    Taproom,
+    Office,
+    Chapel,
+    Dormitory,
+    Armory,
+    Stable,
+}
+
+impl RoomPurpose {
+    /// Colour of the rooms of this purpose in the floor plan images
+    #[must_use]
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            RoomPurpose::Hall => [230, 200, 120],
+            RoomPurpose::LivingRoom => [240, 170, 100],
+            RoomPurpose::Kitchen => [250, 230, 90],
+            RoomPurpose::Bedroom => [120, 170, 240],
+            RoomPurpose::Storage => [150, 120, 90],
+            RoomPurpose::Workroom => [200, 110, 60],
+            RoomPurpose::ShopFloor => [110, 220, 130],
+            RoomPurpose::Taproom => [210, 90, 90],
+            RoomPurpose::Office => [180, 180, 220],
+            RoomPurpose::Chapel => [250, 250, 250],
+            RoomPurpose::Dormitory => [90, 130, 200],
+            RoomPurpose::Armory => [140, 140, 140],
+            RoomPurpose::Stable => [170, 140, 60],
+        }
+    }
+
+    /// Purpose of the room behind the entrance, then of the other rooms from the closest to the
+    /// farthest, the list is repeated if there are more rooms
+    fn for_building(building_type: BuildingType) -> (Self, &'static [Self]) {
+        match building_type {
+            BuildingType::House => (
+                Self::LivingRoom,
+                &[Self::Kitchen, Self::Bedroom, Self::Bedroom, Self::Storage],
+            ),
+            BuildingType::Farm => (
+                Self::Kitchen,
+                &[Self::Stable, Self::Bedroom, Self::Storage, Self::Bedroom],
+            ),
+            BuildingType::Shop => (
+                Self::ShopFloor,
+                &[Self::Storage, Self::Kitchen, Self::Bedroom],
+            ),
+            BuildingType::Workshop => (
+                Self::Workroom,
+                &[Self::Storage, Self::Workroom, Self::Bedroom],
+            ),
+            BuildingType::Tavern => (
+                Self::Taproom,
+                &[Self::Kitchen, Self::Storage, Self::Bedroom, Self::Bedroom],
+            ),
+            BuildingType::Warehouse => (Self::Storage, &[Self::Office, Self::Storage]),
+            BuildingType::TownHall | BuildingType::Guildhall => (
+                Self::Hall,
+                &[Self::Office, Self::Office, Self::Storage, Self::Kitchen],
+            ),
+            BuildingType::Market => (Self::ShopFloor, &[Self::Storage, Self::Office]),
+            BuildingType::Temple => (
+                Self::Chapel,
+                &[Self::Office, Self::Dormitory, Self::Storage],
+            ),
+            BuildingType::Barracks => (
+                Self::Hall,
+                &[
+                    Self::Dormitory,
+                    Self::Armory,
+                    Self::Kitchen,
+                    Self::Dormitory,
+                ],
+            ),
+        }
+    }
+}
+
+/// Room of a building
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Room {
+    /// Unique identifier in the floor plan, rooms are sorted from the entrance
+    pub id: usize,
+    pub purpose: RoomPurpose,
+    /// Floor cells of the room
+    pub cells: Vec<(i32, i32)>,
+}
+
+/// Door between two rooms
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InteriorDoor {
+    /// Coordinates of the door, in a wall cell
+    pub pos: (i32, i32),
+    /// IDs of the rooms on each side of the door
+    pub rooms: (usize, usize),
+}
+
+/// Interior layout of a building, in the same coordinates as its footprint
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct FloorPlan {
+    pub rooms: Vec<Room>,
+    /// Outer and inner wall cells, without the doors
+    pub walls: Vec<(i32, i32)>,
+    pub doors: Vec<InteriorDoor>,
+    /// Door of the building
+    pub entrance: (i32, i32),
+}
+
+/// Random floor plan generator, splits buildings recursively (binary space partitioning)
+pub struct FloorPlanGenerator {
+    rng: ChaCha8Rng,
+    /// Rooms are split until both of their sides are smaller than this
+    max_room_size: i32,
+    /// Minimum length of a side of a room
+    min_room_size: i32,
+}
+
+impl FloorPlanGenerator {
+    #[must_use]
+    pub fn new(seed: u64, min_room_size: i32, max_room_size: i32) -> Self {
+        Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            max_room_size,
+            min_room_size,
+        }
+    }
+
+    /// Subdivide a building into rooms, every room can be reached from the `entrance` door
+    pub fn generate(
+        &mut self,
+        footprint: &Footprint,
+        entrance: (i32, i32),
+        building_type: BuildingType,
+    ) -> FloorPlan {
+        let outer_walls = footprint
+            .boundary_cells()
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let mut walls = outer_walls.clone();
+        let inside = footprint.cells().into_iter().collect::<HashSet<_>>();
+        let floor_box = footprint.bounding_box();
+        self.split(
+            (
+                floor_box.0 + 1,
+                floor_box.1 + 1,
+                floor_box.2 - 1,
+                floor_box.3 - 1,
+            ),
+            &inside,
+            &mut walls,
+        );
+
+        // The cell behind the entrance is always free
+        let Some(hall) = neighbours(entrance)
+            .into_iter()
+            .find(|pos| inside.contains(pos) && !outer_walls.contains(pos))
+        else {
+            let mut walls = walls.into_iter().collect::<Vec<_>>();
+            walls.sort_unstable();
+            return FloorPlan {
+                walls,
+                entrance,
+                ..Default::default()
+            };
+        };
+        walls.remove(&hall);
+
+        let mut labels = label_rooms(&inside, &walls);
+        // Merge tiny rooms by removing the walls around them
+        loop {
+            let mut sizes = HashMap::new();
+            for label in labels.values() {
+                *sizes.entry(*label).or_insert(0) += 1;
+            }
+            let tiny_walls = walls
+                .iter()
+                .filter(|&&wall| {
+                    !outer_walls.contains(&wall)
+                        && neighbours(wall)
+                            .iter()
+                            .filter_map(|n| labels.get(n))
+                            .any(|label| sizes[label] < MIN_ROOM_CELLS)
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            if tiny_walls.is_empty() || sizes.len() == 1 {
+                break;
+            }
+            for wall in tiny_walls {
+                walls.remove(&wall);
+            }
+            labels = label_rooms(&inside, &walls);
+        }
+
+        let (order, doors) =
+            self.connect_all_rooms(&mut labels, &inside, &mut walls, &outer_walls, hall);
+        // Parts of the footprint cut off by its outer walls can't be opened, they are filled
+        let mut rooms_cells = vec![vec![]; order.len()];
+        let mut sorted_cells = labels.iter().collect::<Vec<_>>();
+        sorted_cells.sort_unstable();
+        for (pos, label) in sorted_cells {
+            match order.iter().position(|l| l == label) {
+                Some(id) => rooms_cells[id].push(*pos),
+                None => {
+                    walls.insert(*pos);
+                }
+            }
+        }
+
+        let (first, others) = RoomPurpose::for_building(building_type);
+        let rooms = rooms_cells
+            .into_iter()
+            .enumerate()
+            .map(|(id, cells)| Room {
+                id,
+                purpose: if id == 0 {
+                    first
+                } else {
+                    others[(id - 1) % others.len()]
+                },
+                cells,
+            })
+            .collect();
+
+        let door_cells = doors.iter().map(|d| d.pos).collect::<HashSet<_>>();
+        let mut walls = walls
+            .into_iter()
+            .filter(|pos| *pos != entrance && !door_cells.contains(pos))
+            .collect::<Vec<_>>();
+        walls.sort_unstable();
+        FloorPlan {
+            rooms,
+            walls,
+            doors: doors
+                .into_iter()
+                .map(|door| InteriorDoor {
+                    pos: door.pos,
+                    rooms: (
+                        order.iter().position(|l| *l == door.rooms.0).unwrap(),
+                        order.iter().position(|l| *l == door.rooms.1).unwrap(),
+                    ),
+                })
+                .collect(),
+            entrance,
+        }
+    }
+
+    /// Recursively split an area with walls, bounds are `(min_x, min_y, max_x, max_y)`
+    fn split(
+        &mut self,
+        area: (i32, i32, i32, i32),
+        inside: &HashSet<(i32, i32)>,
+        walls: &mut HashSet<(i32, i32)>,
+    ) {
+        let (min_x, min_y, max_x, max_y) = area;
+        let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+        if width <= self.max_room_size && height <= self.max_room_size {
+            return;
+        }
+        // Split along the longest side
+        let vertical = width >= height;
+        let (start, end) = if vertical {
+            (min_x, max_x)
+        } else {
+            (min_y, max_y)
+        };
+        if end - start < 2 * self.min_room_size {
+            return;
+        }
+        let wall = self
+            .rng
+            .gen_range(start + self.min_room_size..=end - self.min_room_size);
+        if vertical {
+            walls.extend(
+                (min_y..=max_y)
+                    .map(|y| (wall, y))
+                    .filter(|p| inside.contains(p)),
+            );
+            self.split((min_x, min_y, wall - 1, max_y), inside, walls);
+            self.split((wall + 1, min_y, max_x, max_y), inside, walls);
+        } else {
+            walls.extend(
+                (min_x..=max_x)
+                    .map(|x| (x, wall))
+                    .filter(|p| inside.contains(p)),
+            );
+            self.split((min_x, min_y, max_x, wall - 1), inside, walls);
+            self.split((min_x, wall + 1, max_x, max_y), inside, walls);
+        }
+    }
+
+    /// Add doors until every room can be reached from the `hall` cell, rooms separated by more
+    /// than a wall cell are merged into a reached neighbour by removing the walls in between
+    ///
+    /// The floor cells are relabelled after every merge. Returns the labels of the reachable rooms
+    /// in the order they were reached and the doors, with room labels
+    fn connect_all_rooms(
+        &mut self,
+        labels: &mut HashMap<(i32, i32), usize>,
+        inside: &HashSet<(i32, i32)>,
+        walls: &mut HashSet<(i32, i32)>,
+        outer_walls: &HashSet<(i32, i32)>,
+        hall: (i32, i32),
+    ) -> (Vec<usize>, Vec<InteriorDoor>) {
+        loop {
+            let (order, doors) = self.connect_rooms(labels, walls, outer_walls, labels[&hall]);
+            let Some(path) = wall_path(labels, walls, outer_walls, &order) else {
+                return (order, doors);
+            };
+            for pos in path {
+                walls.remove(&pos);
+            }
+            *labels = label_rooms(inside, walls);
+        }
+    }
+
+    /// Add doors until every room can be reached from the `entrance` room
+    ///
+    /// Rooms touching only at a corner are connected through the wall cell next to both corners.
+    /// Returns the labels of the reachable rooms in the order they were reached and the doors,
+    /// with room labels
+    fn connect_rooms(
+        &mut self,
+        labels: &HashMap<(i32, i32), usize>,
+        walls: &HashSet<(i32, i32)>,
+        outer_walls: &HashSet<(i32, i32)>,
+        entrance: usize,
+    ) -> (Vec<usize>, Vec<InteriorDoor>) {
+        // Wall cells separating two rooms, with the rooms on each side
+        let mut connections: HashMap<(usize, usize), Vec<(i32, i32)>> = HashMap::new();
+        // Wall cells in the corner of two rooms, only used when they share no straight wall
+        let mut corners: HashMap<(usize, usize), Vec<(i32, i32)>> = HashMap::new();
+        let mut sorted_walls = walls
+            .iter()
+            .filter(|wall| !outer_walls.contains(wall))
+            .copied()
+            .collect::<Vec<_>>();
+        sorted_walls.sort_unstable();
+        for (x, y) in sorted_walls {
+            for (a, b) in [((x - 1, y), (x + 1, y)), ((x, y - 1), (x, y + 1))] {
+                if let (Some(&la), Some(&lb)) = (labels.get(&a), labels.get(&b)) {
+                    if la != lb {
+                        connections
+                            .entry((la.min(lb), la.max(lb)))
+                            .or_default()
+                            .push((x, y));
+                    }
+                }
+            }
+            for (a, b) in [
+                ((x - 1, y), (x, y - 1)),
+                ((x, y - 1), (x + 1, y)),
+                ((x + 1, y), (x, y + 1)),
+                ((x, y + 1), (x - 1, y)),
+            ] {
+                if let (Some(&la), Some(&lb)) = (labels.get(&a), labels.get(&b)) {
+                    if la != lb {
+                        corners
+                            .entry((la.min(lb), la.max(lb)))
+                            .or_default()
+                            .push((x, y));
+                    }
+                }
+            }
+        }
+        for (rooms, cells) in corners {
+            connections.entry(rooms).or_insert(cells);
+        }
+
+        // Breadth-first search from the entrance, one door per newly reached room
+        let mut order = vec![entrance];
+        let mut doors = vec![];
+        let mut queue = VecDeque::from([entrance]);
+        while let Some(room) = queue.pop_front() {
+            let mut neighbours = connections
+                .keys()
+                .filter_map(|&(a, b)| match (a == room, b == room) {
+                    (true, _) => Some(b),
+                    (_, true) => Some(a),
+                    _ => None,
+                })
+                .filter(|other| !order.contains(other))
+                .collect::<Vec<_>>();
+            neighbours.sort_unstable();
+            for other in neighbours {
+                let cells = &connections[&(room.min(other), room.max(other))];
+                doors.push(InteriorDoor {
+                    pos: *cells.choose(&mut self.rng).unwrap(),
+                    rooms: (room, other),
+                });
+                order.push(other);
+                queue.push_back(other);
+            }
+        }
+        (order, doors)
+    }
+}
+
+/// Give the same label to the floor cells connected together
+fn label_rooms(
+    inside: &HashSet<(i32, i32)>,
+    walls: &HashSet<(i32, i32)>,
+) -> HashMap<(i32, i32), usize> {
+    let mut floor = inside
+        .iter()
+        .filter(|pos| !walls.contains(pos))
+        .copied()
+        .collect::<Vec<_>>();
+    floor.sort_unstable();
+
+    let mut labels = HashMap::new();
+    let mut next_label = 0;
+    for start in floor {
+        if labels.contains_key(&start) {
+            continue;
+        }
+        let mut stack = vec![start];
+        labels.insert(start, next_label);
+        while let Some(pos) = stack.pop() {
+            for neighbour in neighbours(pos) {
+                if inside.contains(&neighbour)
+                    && !walls.contains(&neighbour)
+                    && !labels.contains_key(&neighbour)
+                {
+                    labels.insert(neighbour, next_label);
+                    stack.push(neighbour);
+                }
+            }
+        }
+        next_label += 1;
+    }
+    labels
+}
+
+/// Shortest path through inner walls from a room in `reached` to any other room
+///
+/// Returns the wall cells of the path, `None` if every room is reached or the other rooms are
+/// cut off by the outer walls.
+fn wall_path(
+    labels: &HashMap<(i32, i32), usize>,
+    walls: &HashSet<(i32, i32)>,
+    outer_walls: &HashSet<(i32, i32)>,
+    reached: &[usize],
+) -> Option<Vec<(i32, i32)>> {
+    let mut starts = labels
+        .iter()
+        .filter(|(_, label)| reached.contains(label))
+        .map(|(pos, _)| *pos)
+        .collect::<Vec<_>>();
+    starts.sort_unstable();
+    let mut previous = starts
+        .iter()
+        .map(|pos| (*pos, None))
+        .collect::<HashMap<_, _>>();
+    let mut queue = starts.into_iter().collect::<VecDeque<_>>();
+    while let Some(pos) = queue.pop_front() {
+        for neighbour in neighbours(pos) {
+            if previous.contains_key(&neighbour) {
+                continue;
+            }
+            if labels.contains_key(&neighbour) {
+                // Reached another room, walk back to the first room
+                let mut path = vec![];
+                let mut current = Some(pos);
+                while let Some(cell) = current.filter(|cell| walls.contains(cell)) {
+                    path.push(cell);
+                    current = previous[&cell];
+                }
+                return Some(path);
+            }
+            if walls.contains(&neighbour) && !outer_walls.contains(&neighbour) {
+                previous.insert(neighbour, Some(pos));
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{label_rooms, FloorPlanGenerator};
+    use crate::city_generation::{neighbours, BuildingType, Corner, Footprint};
+
+    #[test]
+    fn test_every_room_is_reachable() {
+        let footprints = [
+            Footprint::rectangle(0, 0, 40, 25),
+            Footprint::l_shape(10, -5, 30, 30, (12, 15), Corner::SouthWest),
+            Footprint::rotated_rectangle((0., 0.), 30, 18, 0.6),
+        ];
+        for (seed, footprint) in footprints.iter().enumerate() {
+            let entrance = footprint.door_candidates()[0];
+            let floor_plan = FloorPlanGenerator::new(seed as u64, 4, 10).generate(
+                footprint,
+                entrance,
+                BuildingType::House,
+            );
+            assert!(floor_plan.rooms.len() > 1);
+
+            let walkable = floor_plan
+                .rooms
+                .iter()
+                .flat_map(|room| room.cells.iter().copied())
+                .chain(floor_plan.doors.iter().map(|door| door.pos))
+                .collect::<HashSet<_>>();
+            let mut reached = HashSet::from([entrance]);
+            let mut stack = vec![entrance];
+            while let Some(pos) = stack.pop() {
+                for neighbour in neighbours(pos) {
+                    if walkable.contains(&neighbour) && reached.insert(neighbour) {
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            assert!(walkable.iter().all(|pos| reached.contains(pos)));
+        }
+    }
+
+    #[test]
+    fn test_rooms_touching_at_a_corner() {
+        // Two rooms touching at the corner of a 4x4 floor, the rest is walls
+        let inside = (0..4)
+            .flat_map(|x| (0..4).map(move |y| (x, y)))
+            .collect::<HashSet<_>>();
+        let mut walls = inside
+            .iter()
+            .filter(|(x, y)| (*x < 2) != (*y < 2))
+            .copied()
+            .collect::<HashSet<_>>();
+        let mut labels = label_rooms(&inside, &walls);
+        let (order, doors) = FloorPlanGenerator::new(0, 1, 2).connect_all_rooms(
+            &mut labels,
+            &inside,
+            &mut walls,
+            &HashSet::new(),
+            (0, 0),
+        );
+        assert_eq!(order.len(), 2);
+        assert_eq!(walls.len(), 8);
+        assert_eq!(doors.len(), 1);
+        // The door is next to a cell of each room
+        let door = doors[0].pos;
+        assert!([doors[0].rooms.0, doors[0].rooms.1]
+            .iter()
+            .all(|room| neighbours(door)
+                .iter()
+                .any(|pos| labels.get(pos) == Some(room))));
+    }
+
+    #[test]
+    fn test_rooms_behind_thick_walls_are_merged() {
+        // Two rooms separated by a wall two cells thick, inside an outer wall
+        let inside = (0..8)
+            .flat_map(|x| (0..4).map(move |y| (x, y)))
+            .collect::<HashSet<_>>();
+        let outer_walls = inside
+            .iter()
+            .filter(|(x, y)| *x == 0 || *x == 7 || *y == 0 || *y == 3)
+            .copied()
+            .collect::<HashSet<_>>();
+        let mut walls = inside
+            .iter()
+            .filter(|(x, _)| *x == 3 || *x == 4)
+            .chain(&outer_walls)
+            .copied()
+            .collect::<HashSet<_>>();
+        let mut labels = label_rooms(&inside, &walls);
+        assert_eq!(labels.values().collect::<HashSet<_>>().len(), 2);
+        let (order, doors) = FloorPlanGenerator::new(0, 1, 2).connect_all_rooms(
+            &mut labels,
+            &inside,
+            &mut walls,
+            &outer_walls,
+            (1, 1),
+        );
+        assert_eq!(order.len(), 1);
+        assert!(doors.is_empty());
+        // The room was opened through the two wall cells between them
+        assert_eq!(labels.len(), 10);
+        assert!(outer_walls.iter().all(|pos| walls.contains(pos)));
+    }
+}
@@ -0,0 +1,369 @@
+//! Export of the generated content as `GeoJSON`, to review it in GIS viewers
+//!
+//! Coordinates are in a local planar CRS where one unit is one cell. The y axis of the city is
+//! flipped so that north is up, as in the rendered images.
+
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::{city_generation::CityGenerator, WorldGen};
+
+/// Closed line, the last vertex is connected to the first one
+type Ring = Vec<(i32, i32)>;
+
+/// Buildings, roads, wall and bounds of a city as a `FeatureCollection`
+///
+/// Every feature has a `kind` property, buildings also have their `id`, `type`, if they are
+/// `important` and the position of their main `door`. Roads know if they are `main` ones.
+#[must_use]
+pub fn city_geojson(city_gen: &CityGenerator) -> Value {
+    let flip = |&(x, y): &(i32, i32)| (x, -y);
+    let mut features = vec![];
+    for building in city_gen.buildings.values() {
+        let vertices = building.footprint.vertices.iter().map(flip).collect();
+        let (door_x, door_y) = flip(&building.door());
+        features.push(feature(
+            polygon(&[vertices]),
+            json!({
+                "kind": "building",
+                "id": building.id,
+                "type": building.building_type,
+                "important": building.is_important,
+                "door": [door_x, door_y],
+            }),
+        ));
+    }
+    for (i, road) in city_gen.roads.iter().enumerate() {
+        let line = simplify(&road.iter().map(flip).collect::<Vec<_>>(), false);
+        if line.len() < 2 {
+            continue;
+        }
+        features.push(feature(
+            json!({ "type": "LineString", "coordinates": coordinates(&line) }),
+            json!({ "kind": "road", "main": i < city_gen.main_roads }),
+        ));
+    }
+    if let Some(wall) = &city_gen.wall {
+        let outline = wall.outline.iter().map(flip).collect();
+        features.push(feature(
+            polygon(&[outline]),
+            json!({ "kind": "wall", "gates": wall.gates.len(), "towers": wall.towers.len() }),
+        ));
+    }
+    let (min_x, min_y, max_x, max_y) = (
+        city_gen.min_x,
+        city_gen.min_y,
+        city_gen.max_x,
+        city_gen.max_y,
+    );
+    let bounds = [
+        (min_x, min_y),
+        (max_x, min_y),
+        (max_x, max_y),
+        (min_x, max_y),
+    ]
+    .iter()
+    .map(flip)
+    .collect();
+    features.push(feature(polygon(&[bounds]), json!({ "kind": "bounds" })));
+    feature_collection(features)
+}
+
+/// Biome regions of `size` cells starting at `origin`, as a `FeatureCollection` of polygons
+///
+/// Cells of the same biome connected by a side are merged in one polygon, with holes where
+/// other biomes are enclosed. Cell `(x, y)` covers the square from `(x, y)` to `(x + 1, y + 1)`.
+#[must_use]
+#[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+pub fn biome_geojson(world_gen: &WorldGen, origin: (isize, isize), size: (usize, usize)) -> Value {
+    let (width, height) = size;
+    let biomes = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            (0..width).map(move |x| {
+                world_gen
+                    .generate_cell((origin.0 + x as isize, origin.1 + y as isize))
+                    .biome
+            })
+        })
+        .collect::<Vec<_>>();
+    let features = regions(&biomes, width, height)
+        .into_iter()
+        .map(|(biome, rings)| {
+            let rings = rings
+                .into_iter()
+                .map(|ring| {
+                    ring.into_iter()
+                        .map(|(x, y)| (x + origin.0 as i32, y + origin.1 as i32))
+                        .collect()
+                })
+                .collect::<Vec<_>>();
+            let [r, g, b] = biome.color();
+            feature(
+                polygon(&rings),
+                json!({
+                    "kind": "biome",
+                    "biome": format!("{biome:?}"),
+                    "color": format!("#{r:02x}{g:02x}{b:02x}"),
+                }),
+            )
+        })
+        .collect();
+    feature_collection(features)
+}
+
+fn feature_collection(features: Vec<Value>) -> Value {
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+fn feature(geometry: Value, properties: Value) -> Value {
+    json!({ "type": "Feature", "geometry": geometry, "properties": properties })
+}
+
+/// Polygon from its exterior ring followed by its holes, closed and oriented as `GeoJSON` wants:
+/// counterclockwise for the exterior, clockwise for the holes
+fn polygon(rings: &[Ring]) -> Value {
+    let rings = rings
+        .iter()
+        .enumerate()
+        .map(|(i, ring)| {
+            let mut ring = ring.clone();
+            if (signed_area(&ring) > 0) != (i == 0) {
+                ring.reverse();
+            }
+            if let Some(&first) = ring.first() {
+                ring.push(first);
+            }
+            coordinates(&ring)
+        })
+        .collect::<Vec<_>>();
+    json!({ "type": "Polygon", "coordinates": rings })
+}
+
+fn coordinates(points: &[(i32, i32)]) -> Value {
+    points.iter().map(|(x, y)| json!([x, y])).collect()
+}
+
+/// Twice the area of a ring, positive if it is counterclockwise with the y axis pointing up
+fn signed_area(ring: &[(i32, i32)]) -> i64 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|((x1, y1), (x2, y2))| {
+            i64::from(*x1) * i64::from(*y2) - i64::from(*x2) * i64::from(*y1)
+        })
+        .sum()
+}
+
+/// Remove the points in the middle of straight parts, `closed` lines loop back to their start
+pub(crate) fn simplify(points: &[(i32, i32)], closed: bool) -> Vec<(i32, i32)> {
+    let n = points.len();
+    if n < 3 {
+        return points.to_vec();
+    }
+    (0..n)
+        .filter(|&i| {
+            if !closed && (i == 0 || i == n - 1) {
+                return true;
+            }
+            let (px, py) = points[(i + n - 1) % n];
+            let (x, y) = points[i];
+            let (nx, ny) = points[(i + 1) % n];
+            (x - px) * (ny - y) != (y - py) * (nx - x)
+        })
+        .map(|i| points[i])
+        .collect()
+}
+
+/// Regions of cells with the same label connected by a side, with their outline
+///
+/// `labels` are stored row by row. The exterior ring of every region comes first, followed by
+/// its holes.
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn regions<T: Copy + PartialEq>(labels: &[T], width: usize, height: usize) -> Vec<(T, Vec<Ring>)> {
+    let mut region_of = vec![usize::MAX; labels.len()];
+    let mut regions = vec![];
+    for start in 0..labels.len() {
+        if region_of[start] != usize::MAX {
+            continue;
+        }
+        let region = regions.len();
+        let label = labels[start];
+        let mut cells = vec![];
+        let mut stack = vec![start];
+        region_of[start] = region;
+        while let Some(i) = stack.pop() {
+            cells.push(i);
+            let (x, y) = (i % width, i / width);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - width),
+                (y + 1 < height).then(|| i + width),
+            ];
+            for j in neighbours.into_iter().flatten() {
+                if region_of[j] == usize::MAX && labels[j] == label {
+                    region_of[j] = region;
+                    stack.push(j);
+                }
+            }
+        }
+        regions.push((label, cells));
+    }
+
+    regions
+        .into_iter()
+        .enumerate()
+        .map(|(region, (label, cells))| {
+            let inside = |x: i32, y: i32| {
+                x >= 0
+                    && y >= 0
+                    && (x as usize) < width
+                    && (y as usize) < height
+                    && region_of[y as usize * width + x as usize] == region
+            };
+            // Sides of the cells on the border of the region, going counterclockwise around it
+            let mut edges = HashMap::<(i32, i32), Vec<(i32, i32)>>::new();
+            for i in cells {
+                let (x, y) = ((i % width) as i32, (i / width) as i32);
+                for (neighbour, from, to) in [
+                    ((x, y - 1), (x, y), (x + 1, y)),
+                    ((x + 1, y), (x + 1, y), (x + 1, y + 1)),
+                    ((x, y + 1), (x + 1, y + 1), (x, y + 1)),
+                    ((x - 1, y), (x, y + 1), (x, y)),
+                ] {
+                    if !inside(neighbour.0, neighbour.1) {
+                        edges.entry(from).or_default().push(to);
+                    }
+                }
+            }
+            let mut rings = trace_rings(edges);
+            // A region has a single exterior ring, the only counterclockwise one
+            rings.sort_by_key(|ring| std::cmp::Reverse(signed_area(ring)));
+            (label, rings)
+        })
+        .collect()
+}
+
+/// Chain directed edges in rings, turning left where two rings touch to keep them apart
+fn trace_rings(mut edges: HashMap<(i32, i32), Vec<(i32, i32)>>) -> Vec<Ring> {
+    let mut starts = edges.keys().copied().collect::<Vec<_>>();
+    starts.sort_unstable();
+    let mut rings = vec![];
+    for start in starts {
+        while let Some(next) = edges.get_mut(&start).and_then(Vec::pop) {
+            let mut ring = vec![start];
+            let (mut previous, mut current) = (start, next);
+            while current != start {
+                ring.push(current);
+                let direction = (current.0 - previous.0, current.1 - previous.1);
+                let outgoing = edges.get_mut(&current).unwrap();
+                // Cross product of the directions, positive for a left turn
+                let turn = |to: &(i32, i32)| {
+                    direction.0 * (to.1 - current.1) - direction.1 * (to.0 - current.0)
+                };
+                let best = (0..outgoing.len())
+                    .max_by_key(|&i| turn(&outgoing[i]))
+                    .unwrap();
+                previous = current;
+                current = outgoing.swap_remove(best);
+            }
+            rings.extend(split_loops(ring).iter().map(|ring| simplify(ring, true)));
+        }
+    }
+    rings
+}
+
+/// Split a ring going several times through the same vertex in simple loops
+///
+/// Regions touching themselves by a corner give such rings, the loops are their exterior and
+/// holes touching it.
+fn split_loops(ring: Ring) -> Vec<Ring> {
+    let mut loops = vec![];
+    let mut path: Ring = vec![];
+    let mut positions = HashMap::new();
+    for vertex in ring {
+        if let Some(start) = positions.get(&vertex).copied() {
+            let closed = path.split_off(start + 1);
+            for pos in &closed {
+                positions.remove(pos);
+            }
+            loops.push([vec![vertex], closed].concat());
+        } else {
+            positions.insert(vertex, path.len());
+            path.push(vertex);
+        }
+    }
+    loops.push(path);
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{city_geojson, regions, signed_area};
+    use crate::city_generation::{CityConfig, CityGenerator};
+
+    #[test]
+    fn test_regions() {
+        // A ring of 1 around a 0, the 0 on the right and on the bottom only touch by a corner
+        #[rustfmt::skip]
+        let labels = [
+            1, 1, 1, 0,
+            1, 0, 1, 0,
+            1, 1, 1, 0,
+            0, 0, 0, 2,
+        ];
+        let regions = regions(&labels, 4, 4);
+        assert_eq!(regions.len(), 5);
+        let (label, rings) = &regions[0];
+        assert_eq!(*label, 1);
+        assert_eq!(rings.len(), 2);
+        assert_eq!(rings[0].len(), 4);
+        assert_eq!(signed_area(&rings[0]), 2 * 9);
+        assert_eq!(signed_area(&rings[1]), -2);
+        let (label, rings) = &regions[1];
+        assert_eq!(*label, 0);
+        assert_eq!(rings.len(), 1);
+        assert_eq!(signed_area(&rings[0]), 2 * 3);
+    }
+
+    #[test]
+    fn test_regions_touching_holes() {
+        // Holes touching by a corner stay separate rings
+        #[rustfmt::skip]
+        let labels = [
+            0, 0, 0, 0,
+            0, 1, 0, 0,
+            0, 0, 1, 0,
+            0, 0, 0, 0,
+        ];
+        let regions = regions(&labels, 4, 4);
+        let (_, rings) = &regions[0];
+        assert_eq!(rings.len(), 3);
+        assert_eq!(signed_area(&rings[0]), 2 * 16);
+        assert!(rings[1..].iter().all(|ring| signed_area(ring) == -2));
+    }
+
+    #[test]
+    fn test_city_geojson() {
+        let mut city_gen = CityGenerator::new(CityConfig::default().set_seed(1));
+        city_gen.generate().unwrap();
+        let geojson = city_geojson(&city_gen);
+        let features = geojson["features"].as_array().unwrap();
+        let count = |kind: &str| {
+            features
+                .iter()
+                .filter(|f| f["properties"]["kind"] == kind)
+                .count()
+        };
+        assert_eq!(count("building"), city_gen.buildings.len());
+        assert_eq!(count("bounds"), 1);
+        assert!(count("road") > 0);
+        let building = features
+            .iter()
+            .find(|f| f["properties"]["kind"] == "building")
+            .unwrap();
+        let ring = building["geometry"]["coordinates"][0].as_array().unwrap();
+        assert_eq!(ring.first(), ring.last());
+    }
+}
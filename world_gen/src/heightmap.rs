@@ -0,0 +1,244 @@
+//! Export of the height of the cells for terrain tools
+
+use clap::ValueEnum;
+use image::{ImageBuffer, ImageError, ImageResult, Luma};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{fs::File, io::BufWriter, path::Path};
+use tiff::encoder::{colortype::Gray32Float, TiffEncoder};
+
+use crate::WorldGen;
+
+/// File format of a heightmap
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeightmapFormat {
+    /// 16-bit grayscale PNG, normalised
+    Png16,
+    /// Raw little-endian unsigned 16-bit integers, normalised
+    R16,
+    /// Raw little-endian 32-bit floats, heights as generated
+    R32,
+    /// 32-bit float grayscale TIFF, heights as generated
+    Tiff,
+}
+
+impl HeightmapFormat {
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png16 => "png",
+            Self::R16 => "r16",
+            Self::R32 => "r32",
+            Self::Tiff => "tif",
+        }
+    }
+}
+
+/// Description of a heightmap to import it, saved next to it as JSON
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HeightmapMetadata {
+    pub width: usize,
+    pub height: usize,
+    /// Coordinates of the south west cell, the first row is the northmost one
+    pub origin: (isize, isize),
+    /// Height mapped to 0 in the normalised formats
+    pub min: f64,
+    /// Height mapped to the max value in the normalised formats
+    pub max: f64,
+    /// Formats written, with their extension
+    pub formats: Vec<HeightmapFormat>,
+    /// How normalised values are computed
+    pub normalisation: &'static str,
+}
+
+/// Height of a rectangle of cells, row by row with the northmost row first
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    pub width: usize,
+    pub height: usize,
+    /// Coordinates of the south west cell, the one with the smallest x and y
+    pub origin: (isize, isize),
+    pub values: Vec<f32>,
+    /// Height mapped to 0 when normalising
+    pub min: f64,
+    /// Height mapped to the max value when normalising
+    pub max: f64,
+}
+
+impl Heightmap {
+    /// Heights of `size` cells starting at `origin`, normalised with their own min and max
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )]
+    pub fn generate(world_gen: &WorldGen, origin: (isize, isize), size: (usize, usize)) -> Self {
+        let (width, height) = size;
+        let values = (0..height)
+            .into_par_iter()
+            .flat_map_iter(|row| {
+                let y = origin.1 + (height - 1 - row) as isize;
+                (0..width)
+                    .map(move |x| world_gen.generate_cell((origin.0 + x as isize, y)).height as f32)
+            })
+            .collect::<Vec<_>>();
+        let (min, max) = values.iter().fold((f64::MAX, f64::MIN), |(min, max), v| {
+            (min.min(f64::from(*v)), max.max(f64::from(*v)))
+        });
+        Self {
+            width,
+            height,
+            origin,
+            values,
+            min,
+            max,
+        }
+    }
+
+    /// Normalise with a fixed range instead, so that several heightmaps match
+    #[must_use]
+    pub fn set_range(self, min: f64, max: f64) -> Self {
+        Self { min, max, ..self }
+    }
+
+    /// Height between 0 at `min` and 1 at `max`, clamped
+    #[must_use]
+    pub fn normalised(&self, value: f32) -> f64 {
+        if self.max <= self.min {
+            return 0.;
+        }
+        ((f64::from(value) - self.min) / (self.max - self.min)).clamp(0., 1.)
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn normalised_u16(&self) -> Vec<u16> {
+        self.values
+            .iter()
+            .map(|v| (self.normalised(*v) * f64::from(u16::MAX)).round() as u16)
+            .collect()
+    }
+
+    /// Write the heightmap at `path` in the given format
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be written.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn save(&self, path: impl AsRef<Path>, format: HeightmapFormat) -> ImageResult<()> {
+        let (width, height) = (self.width as u32, self.height as u32);
+        match format {
+            HeightmapFormat::Png16 => {
+                ImageBuffer::<Luma<u16>, _>::from_raw(width, height, self.normalised_u16())
+                    .expect("one value per cell")
+                    .save(path)
+            }
+            HeightmapFormat::R16 => {
+                let bytes = self
+                    .normalised_u16()
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect::<Vec<_>>();
+                Ok(std::fs::write(path, bytes)?)
+            }
+            HeightmapFormat::R32 => {
+                let bytes = self
+                    .values
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect::<Vec<_>>();
+                Ok(std::fs::write(path, bytes)?)
+            }
+            HeightmapFormat::Tiff => {
+                let file = BufWriter::new(File::create(path)?);
+                TiffEncoder::new(file)
+                    .and_then(|mut encoder| {
+                        encoder.write_image::<Gray32Float>(width, height, &self.values)
+                    })
+                    .map_err(|e| ImageError::IoError(std::io::Error::other(e)))
+            }
+        }
+    }
+
+    /// Description of the heightmap once saved in `formats`
+    #[must_use]
+    pub fn metadata(&self, formats: &[HeightmapFormat]) -> HeightmapMetadata {
+        HeightmapMetadata {
+            width: self.width,
+            height: self.height,
+            origin: self.origin,
+            min: self.min,
+            max: self.max,
+            formats: formats.to_vec(),
+            normalisation: "png16 and r16 store round((clamp(height, min, max) - min) / (max - min) * 65535), r32 and tif store the height",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Heightmap, HeightmapFormat};
+    use crate::WorldGen;
+
+    fn heightmap() -> Heightmap {
+        Heightmap::generate(&WorldGen::new(1., Some(5)), (-10, 5), (20, 10))
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn test_heightmap() {
+        let world_gen = WorldGen::new(1., Some(5));
+        let heightmap = heightmap();
+        assert_eq!(heightmap.values.len(), 200);
+        // The first row is the northmost one
+        assert_eq!(
+            heightmap.values[20],
+            world_gen.generate_cell((-10, 13)).height as f32
+        );
+        let normalised = heightmap.normalised_u16();
+        assert_eq!(normalised.iter().min(), Some(&0));
+        assert_eq!(normalised.iter().max(), Some(&u16::MAX));
+    }
+
+    #[test]
+    fn test_save_formats() {
+        let heightmap = heightmap();
+        // Unique to the process so that concurrent runs don't share files
+        let dir =
+            std::env::temp_dir().join(format!("world_gen_heightmap_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for format in [
+            HeightmapFormat::Png16,
+            HeightmapFormat::R16,
+            HeightmapFormat::R32,
+            HeightmapFormat::Tiff,
+        ] {
+            heightmap
+                .save(dir.join(format!("h.{}", format.extension())), format)
+                .unwrap();
+        }
+
+        let png = image::open(dir.join("h.png")).unwrap().into_luma16();
+        assert_eq!(png.dimensions(), (20, 10));
+        assert_eq!(png.into_raw(), heightmap.normalised_u16());
+        let r16 = std::fs::read(dir.join("h.r16")).unwrap();
+        assert_eq!(r16.len(), 200 * 2);
+        assert_eq!(
+            u16::from_le_bytes([r16[2], r16[3]]),
+            heightmap.normalised_u16()[1]
+        );
+        let r32 = std::fs::read(dir.join("h.r32")).unwrap();
+        assert_eq!(
+            f32::from_le_bytes([r32[4], r32[5], r32[6], r32[7]]),
+            heightmap.values[1]
+        );
+        let mut decoder =
+            tiff::decoder::Decoder::new(std::fs::File::open(dir.join("h.tif")).unwrap()).unwrap();
+        let tiff::decoder::DecodingResult::F32(values) = decoder.read_image().unwrap() else {
+            panic!("not a float image");
+        };
+        assert_eq!(values, heightmap.values);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -16,3 +16,46 @@ pub fn draw_rect(
         *img.get_pixel_mut(pos.0 + width, pos.1 + y) = color;
     }
 }
+
+/// Draw a line between two points, pixels outside of the image are skipped
+#[allow(clippy::cast_sign_loss)]
+pub fn draw_line(
+    img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    from: (i32, i32),
+    to: (i32, i32),
+    color: Rgb<u8>,
+) {
+    // Bresenham's line algorithm
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (mut x, mut y) = from;
+    let mut error = dx + dy;
+    loop {
+        if x >= 0 && y >= 0 && (x as u32) < img.width() && (y as u32) < img.height() {
+            *img.get_pixel_mut(x as u32, y as u32) = color;
+        }
+        if (x, y) == to {
+            break;
+        }
+        let double_error = 2 * error;
+        if double_error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if double_error <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Draw the outline of a closed polygon
+pub fn draw_polygon(
+    img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    vertices: &[(i32, i32)],
+    color: Rgb<u8>,
+) {
+    for (from, to) in vertices.iter().zip(vertices.iter().cycle().skip(1)) {
+        draw_line(img, *from, *to, color);
+    }
+}
@@ -3,14 +3,33 @@ use cell::Cell;
 use perlin_noise::PerlinNoiseGenerator;
 use rand::{thread_rng, RngCore};
 
+pub mod analysis;
 mod biome;
 pub mod cell;
 pub mod city_generation;
+pub mod floor_plan;
+pub mod geojson;
+pub mod heightmap;
 pub mod image_utils;
+pub mod mesh;
 mod perlin_noise;
+pub mod population;
+pub mod region;
+pub mod render;
+pub mod report;
+pub mod search;
+pub mod spawn;
+pub mod svg;
+pub mod tiled;
+pub mod tiles;
 mod vector;
+
+pub use biome::BiomeType;
+
 /// So that 1.0 is a good scale
 const GLOBAL_SCALE_FIX: f64 = 30.;
+/// Weight of the erosion in the height, added to the continentalness
+const EROSION_WEIGHT: f64 = 0.3;
 #[derive(Debug, Clone)]
 
 pub struct WorldGen {
@@ -81,7 +100,7 @@ impl WorldGen {
             .noise(pos, self.continentalness_scale);
         let erosion = self.erosion_noise.noise(pos, self.erosion_scale);
 
-        let height = continentalness + erosion * 0.3;
+        let height = continentalness + erosion * EROSION_WEIGHT;
 
         Cell {
             temp,
@@ -105,7 +124,7 @@ impl WorldGen {
             .noise(pos, self.continentalness_scale / scale);
         let erosion = self.erosion_noise.noise(pos, self.erosion_scale / scale);
 
-        let height = continentalness + erosion * 0.3;
+        let height = continentalness + erosion * EROSION_WEIGHT;
 
         Cell {
             temp,
@@ -132,4 +151,144 @@ impl WorldGen {
         }
         chunk
     }
+
+    /// Height at a position between cells, only the continentalness and the erosion are
+    /// evaluated
+    #[must_use]
+    pub fn height_at(&self, x: f64, y: f64) -> f64 {
+        let continentalness = self
+            .continentalness_noise
+            .noise((x, y), self.continentalness_scale);
+        let erosion = self.erosion_noise.noise((x, y), self.erosion_scale);
+        continentalness + erosion * EROSION_WEIGHT
+    }
+
+    /// Biome at a position between cells, the same as the cell's at integer positions
+    #[must_use]
+    pub fn biome_at(&self, x: f64, y: f64) -> BiomeType {
+        let temp = self.temperature_noise.noise((x, y), self.temp_scale);
+        let moisture = self.moisture_noise.noise((x, y), self.moisture_scale);
+        BiomeSettings::new(temp, moisture, self.height_at(x, y)).into()
+    }
+
+    /// Height at a position between cells with its variation per cell towards x and y, from
+    /// the analytic derivatives of the noises
+    #[must_use]
+    pub fn height_with_gradient_at(&self, x: f64, y: f64) -> (f64, (f64, f64)) {
+        let (continentalness, (cx, cy)) = self
+            .continentalness_noise
+            .noise_with_gradient((x, y), self.continentalness_scale);
+        let (erosion, (ex, ey)) = self
+            .erosion_noise
+            .noise_with_gradient((x, y), self.erosion_scale);
+        (
+            continentalness + erosion * EROSION_WEIGHT,
+            (
+                ex.mul_add(EROSION_WEIGHT, cx),
+                ey.mul_add(EROSION_WEIGHT, cy),
+            ),
+        )
+    }
+
+    /// Variation of the height per cell towards x and y
+    #[must_use]
+    pub fn gradient_at(&self, x: f64, y: f64) -> (f64, f64) {
+        self.height_with_gradient_at(x, y).1
+    }
+
+    /// Cell with the gradient of its height, without evaluating the height again
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn generate_cell_with_gradient(&self, pos: (isize, isize)) -> (Cell, (f64, f64)) {
+        let pos = (pos.0 as f64, pos.1 as f64);
+        let temp = self.temperature_noise.noise(pos, self.temp_scale);
+        let moisture = self.moisture_noise.noise(pos, self.moisture_scale);
+        let (continentalness, (cx, cy)) = self
+            .continentalness_noise
+            .noise_with_gradient(pos, self.continentalness_scale);
+        let (erosion, (ex, ey)) = self
+            .erosion_noise
+            .noise_with_gradient(pos, self.erosion_scale);
+
+        let height = continentalness + erosion * EROSION_WEIGHT;
+
+        let cell = Cell {
+            temp,
+            moisture,
+            continentalness,
+            erosion,
+            biome: BiomeSettings::new(temp, moisture, height).into(),
+            height,
+        };
+        let gradient = (
+            ex.mul_add(EROSION_WEIGHT, cx),
+            ey.mul_add(EROSION_WEIGHT, cy),
+        );
+        (cell, gradient)
+    }
+
+    /// Unit normal of the terrain with y up, x and z following the cells like the meshes
+    ///
+    /// `height_scale` is the elevation for a height of 1, in cells.
+    #[must_use]
+    pub fn normal_at(&self, x: f64, y: f64, height_scale: f64) -> [f64; 3] {
+        let (dx, dy) = self.gradient_at(x, y);
+        let (dx, dy) = (dx * height_scale, dy * height_scale);
+        let length = dx.mul_add(dx, dy.mul_add(dy, 1.)).sqrt();
+        [-dx / length, 1. / length, -dy / length]
+    }
+
+    /// Slope of the terrain in degrees, `height_scale` being the elevation for a height of 1,
+    /// in cells
+    #[must_use]
+    pub fn slope_at(&self, x: f64, y: f64, height_scale: f64) -> f64 {
+        let (dx, dy) = self.gradient_at(x, y);
+        (dx.hypot(dy) * height_scale).atan().to_degrees()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{analysis::Analysis, region::Region, WorldGen};
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn test_point_queries() {
+        let world_gen = WorldGen::new(1., Some(4));
+        for pos in [(0, 0), (37, -120), (-450, 999)] {
+            let cell = world_gen.generate_cell(pos);
+            let (x, y) = (pos.0 as f64, pos.1 as f64);
+            assert_eq!(world_gen.height_at(x, y), cell.height);
+            assert_eq!(world_gen.biome_at(x, y), cell.biome);
+        }
+
+        // Continuous between the cells
+        let (a, b) = (
+            world_gen.height_at(10., 20.),
+            world_gen.height_at(10.01, 20.),
+        );
+        assert!((a - b).abs() < 1e-3);
+
+        let region = Region::new(-60, 30, 20, 20);
+        let analysis = Analysis::generate(&world_gen, region, Default::default());
+        for (i, slope) in analysis.slope().into_iter().enumerate() {
+            let (x, y) = region.cell(i);
+            let exact = world_gen.slope_at(x as f64, y as f64, 100.);
+            // The same analytic gradient, up to the precision of the map
+            assert!((exact - f64::from(slope)).abs() < 1e-3, "{exact} {slope}");
+            let (cell, gradient) = world_gen.generate_cell_with_gradient((x, y));
+            assert_eq!(cell.height, world_gen.generate_cell((x, y)).height);
+            assert_eq!(gradient, world_gen.gradient_at(x as f64, y as f64));
+            let step = 1e-6;
+            let finite = (world_gen.height_at(x as f64 + step, y as f64)
+                - world_gen.height_at(x as f64 - step, y as f64))
+                / (2. * step);
+            assert!((gradient.0 - finite).abs() < 1e-6);
+            let [nx, ny, nz] = world_gen.normal_at(x as f64 + 0.3, y as f64, 100.);
+            assert!((nx.mul_add(nx, ny.mul_add(ny, nz * nz)) - 1.).abs() < 1e-9);
+            let normal_slope = ny.acos().to_degrees();
+            let slope = world_gen.slope_at(x as f64 + 0.3, y as f64, 100.);
+            assert!((normal_slope - slope).abs() < 1e-6);
+        }
+    }
 }
@@ -0,0 +1,411 @@
+//! Triangulated terrain meshes, exported as OBJ or binary glTF
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde_json::json;
+use std::io::{self, Write};
+
+use crate::WorldGen;
+
+/// File format of a mesh
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MeshFormat {
+    /// Wavefront OBJ, vertex colours follow the positions
+    Obj,
+    /// Binary glTF
+    Glb,
+}
+
+impl MeshFormat {
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Obj => "obj",
+            Self::Glb => "glb",
+        }
+    }
+}
+
+/// Region and shape of a terrain mesh
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshSettings {
+    /// First cell of the region
+    pub origin: [i32; 2],
+    /// Number of cells of the region, there is one more vertex on each side
+    pub size: [u32; 2],
+    /// Number of cells per unit of the mesh
+    pub resolution: u32,
+    /// Height of the mesh for a cell height of 1
+    pub height_scale: f32,
+    /// Depth of the skirts hanging from the borders to hide the gaps between meshes, if any
+    pub skirt_depth: Option<f32>,
+}
+
+impl Default for MeshSettings {
+    fn default() -> Self {
+        Self {
+            origin: [0, 0],
+            size: [64, 64],
+            resolution: 1,
+            height_scale: 10.,
+            skirt_depth: None,
+        }
+    }
+}
+
+impl MeshSettings {
+    #[must_use]
+    pub fn set_origin(self, origin: [i32; 2]) -> Self {
+        Self { origin, ..self }
+    }
+    #[must_use]
+    pub fn set_size(self, size: [u32; 2]) -> Self {
+        Self { size, ..self }
+    }
+    #[must_use]
+    pub fn set_resolution(self, resolution: u32) -> Self {
+        Self { resolution, ..self }
+    }
+    #[must_use]
+    pub fn set_height_scale(self, height_scale: f32) -> Self {
+        Self {
+            height_scale,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_skirt_depth(self, skirt_depth: Option<f32>) -> Self {
+        Self {
+            skirt_depth,
+            ..self
+        }
+    }
+}
+
+/// Triangle list of a terrain, x and z follow the cells and y is up
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TerrainMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Colour of the biome of the vertices, in linear RGBA
+    pub colors: Vec<[f32; 4]>,
+    /// Position of the vertices in the region, from 0 to 1
+    pub uvs: Vec<[f32; 2]>,
+    /// Counterclockwise triangles seen from above
+    pub indices: Vec<u32>,
+}
+
+impl TerrainMesh {
+    /// Triangulate the cells of a region, normals come from the analytic gradient of the
+    /// heights so that adjacent meshes match
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn generate(world_gen: &WorldGen, settings: &MeshSettings) -> Self {
+        let [width, depth] = settings.size.map(|n| n as usize);
+        let (columns, rows) = (width + 1, depth + 1);
+        let resolution = settings.resolution.max(1) as f32;
+        // Row by row, from the southmost row
+        let cells = (0..rows * columns)
+            .into_par_iter()
+            .map(|k| {
+                world_gen.generate_cell_with_gradient((
+                    (settings.origin[0] + (k % columns) as i32) as isize,
+                    (settings.origin[1] + (k / columns) as i32) as isize,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let mut mesh = Self::default();
+        // Vertices are stored along z first, as in the viewer
+        for i in 0..columns {
+            for j in 0..rows {
+                let (x, z) = (
+                    (settings.origin[0] + i as i32) as f32 / resolution,
+                    (settings.origin[1] + j as i32) as f32 / resolution,
+                );
+                let (cell, (gradient_x, gradient_z)) = &cells[j * columns + i];
+                mesh.positions
+                    .push([x, cell.height as f32 * settings.height_scale, z]);
+                // Scaled like the positions, the gradient being per cell
+                let dx = *gradient_x as f32 * settings.height_scale * resolution;
+                let dz = *gradient_z as f32 * settings.height_scale * resolution;
+                let length = dx.mul_add(dx, dz.mul_add(dz, 1.)).sqrt();
+                mesh.normals.push([-dx / length, 1. / length, -dz / length]);
+                let [r, g, b] = cell.biome.color();
+                mesh.colors
+                    .push([srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), 1.]);
+                mesh.uvs.push([
+                    i as f32 / width.max(1) as f32,
+                    j as f32 / depth.max(1) as f32,
+                ]);
+            }
+        }
+        for i in 0..width {
+            for j in 0..depth {
+                let current = (i * rows + j) as u32;
+                let next = current + rows as u32;
+                mesh.indices
+                    .extend([current, current + 1, next, next, current + 1, next + 1]);
+            }
+        }
+        if let Some(skirt_depth) = settings.skirt_depth {
+            mesh.add_skirt(columns, rows, skirt_depth);
+        }
+        mesh
+    }
+
+    /// Hang a vertical strip under the border, going around it counterclockwise from above
+    #[allow(clippy::cast_possible_truncation)]
+    fn add_skirt(&mut self, columns: usize, rows: usize, skirt_depth: f32) {
+        let index = |i: usize, j: usize| i * rows + j;
+        let border = (0..columns - 1)
+            .map(|i| index(i, 0))
+            .chain((0..rows - 1).map(|j| index(columns - 1, j)))
+            .chain((1..columns).rev().map(|i| index(i, rows - 1)))
+            .chain((1..rows).rev().map(|j| index(0, j)))
+            .collect::<Vec<_>>();
+        let first_skirt_vertex = self.positions.len();
+        for &vertex in &border {
+            let [x, y, z] = self.positions[vertex];
+            self.positions.push([x, y - skirt_depth, z]);
+            self.normals.push(self.normals[vertex]);
+            self.colors.push(self.colors[vertex]);
+            self.uvs.push(self.uvs[vertex]);
+        }
+        for k in 0..border.len() {
+            let next = (k + 1) % border.len();
+            let (top, top_next) = (border[k] as u32, border[next] as u32);
+            let (bottom, bottom_next) = (
+                (first_skirt_vertex + k) as u32,
+                (first_skirt_vertex + next) as u32,
+            );
+            // Facing outwards
+            self.indices
+                .extend([top, top_next, bottom, bottom, top_next, bottom_next]);
+        }
+    }
+
+    /// Write the mesh in the given format
+    ///
+    /// # Errors
+    ///
+    /// Fails if the writer does.
+    pub fn write(&self, writer: &mut impl Write, format: MeshFormat) -> io::Result<()> {
+        match format {
+            MeshFormat::Obj => self.write_obj(writer),
+            MeshFormat::Glb => self.write_glb(writer),
+        }
+    }
+
+    /// Write the mesh as Wavefront OBJ, colours are written after the positions
+    ///
+    /// # Errors
+    ///
+    /// Fails if the writer does.
+    pub fn write_obj(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        writeln!(writer, "# Terrain generated by world_gen")?;
+        for ([x, y, z], [r, g, b, _]) in self.positions.iter().zip(&self.colors) {
+            writeln!(writer, "v {x} {y} {z} {r} {g} {b}")?;
+        }
+        for [x, y, z] in &self.normals {
+            writeln!(writer, "vn {x} {y} {z}")?;
+        }
+        for [u, v] in &self.uvs {
+            writeln!(writer, "vt {u} {v}")?;
+        }
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        writer.flush()
+    }
+
+    /// Write the mesh as binary glTF 2.0, with a material using the vertex colours
+    ///
+    /// # Errors
+    ///
+    /// Fails if the writer does.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn write_glb(&self, writer: &mut impl Write) -> io::Result<()> {
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+        let mut buffer = vec![];
+        let mut views = vec![];
+        let mut add_view = |bytes: Vec<u8>, target: u32| {
+            views.push(json!({
+                "buffer": 0,
+                "byteOffset": buffer.len(),
+                "byteLength": bytes.len(),
+                "target": target,
+            }));
+            buffer.extend(bytes);
+            views.len() - 1
+        };
+        let floats = |values: &mut dyn Iterator<Item = f32>| {
+            values.flat_map(f32::to_le_bytes).collect::<Vec<_>>()
+        };
+        let positions = add_view(
+            floats(&mut self.positions.iter().flatten().copied()),
+            ARRAY_BUFFER,
+        );
+        let normals = add_view(
+            floats(&mut self.normals.iter().flatten().copied()),
+            ARRAY_BUFFER,
+        );
+        let colors = add_view(
+            floats(&mut self.colors.iter().flatten().copied()),
+            ARRAY_BUFFER,
+        );
+        let uvs = add_view(
+            floats(&mut self.uvs.iter().flatten().copied()),
+            ARRAY_BUFFER,
+        );
+        let indices = add_view(
+            self.indices.iter().flat_map(|i| i.to_le_bytes()).collect(),
+            ELEMENT_ARRAY_BUFFER,
+        );
+
+        let (min, max) =
+            self.positions
+                .iter()
+                .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), position| {
+                    (
+                        [0, 1, 2].map(|k| min[k].min(position[k])),
+                        [0, 1, 2].map(|k| max[k].max(position[k])),
+                    )
+                });
+        let count = self.positions.len();
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": "world_gen" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0, "name": "terrain" }],
+            "meshes": [{
+                "name": "terrain",
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "NORMAL": 1, "COLOR_0": 2, "TEXCOORD_0": 3 },
+                    "indices": 4,
+                    "material": 0,
+                }],
+            }],
+            "materials": [{
+                "name": "biomes",
+                "pbrMetallicRoughness": { "metallicFactor": 0.0, "roughnessFactor": 1.0 },
+            }],
+            "accessors": [
+                { "bufferView": positions, "componentType": FLOAT, "count": count, "type": "VEC3", "min": min, "max": max },
+                { "bufferView": normals, "componentType": FLOAT, "count": count, "type": "VEC3" },
+                { "bufferView": colors, "componentType": FLOAT, "count": count, "type": "VEC4" },
+                { "bufferView": uvs, "componentType": FLOAT, "count": count, "type": "VEC2" },
+                { "bufferView": indices, "componentType": UNSIGNED_INT, "count": self.indices.len(), "type": "SCALAR" },
+            ],
+            "bufferViews": views,
+            "buffers": [{ "byteLength": buffer.len() }],
+        });
+
+        // Chunks are aligned on 4 bytes, with spaces for the JSON and zeros for the binary
+        let mut json = serde_json::to_vec(&gltf)?;
+        json.resize(json.len().next_multiple_of(4), b' ');
+        buffer.resize(buffer.len().next_multiple_of(4), 0);
+        let length = 12 + 8 + json.len() + 8 + buffer.len();
+
+        writer.write_all(b"glTF")?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(length as u32).to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(b"JSON")?;
+        writer.write_all(&json)?;
+        writer.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        writer.write_all(b"BIN\0")?;
+        writer.write_all(&buffer)?;
+        writer.flush()
+    }
+}
+
+/// Colour component converted from sRGB to the linear space used by meshes
+fn srgb_to_linear(component: u8) -> f32 {
+    let c = f32::from(component) / 255.;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MeshSettings, TerrainMesh};
+    use crate::WorldGen;
+
+    fn small_mesh(skirt_depth: Option<f32>) -> TerrainMesh {
+        let settings = MeshSettings::default()
+            .set_origin([-5, 3])
+            .set_size([8, 4])
+            .set_skirt_depth(skirt_depth);
+        TerrainMesh::generate(&WorldGen::new(1., Some(2)), &settings)
+    }
+
+    #[test]
+    fn test_terrain_mesh() {
+        let mesh = small_mesh(None);
+        assert_eq!(mesh.positions.len(), 9 * 5);
+        assert_eq!(mesh.indices.len(), 8 * 4 * 6);
+        assert_eq!(mesh.positions[0][0], -5.);
+        assert_eq!(mesh.positions[0][2], 3.);
+        for normal in &mesh.normals {
+            let length = normal.iter().map(|n| n * n).sum::<f32>().sqrt();
+            assert!((length - 1.).abs() < 1e-4 && normal[1] > 0.);
+        }
+
+        // 24 border vertices, two triangles each
+        let skirted = small_mesh(Some(2.));
+        assert_eq!(skirted.positions.len(), 9 * 5 + 24);
+        assert_eq!(skirted.indices.len(), 8 * 4 * 6 + 24 * 6);
+        assert!(skirted.indices.iter().all(|i| (*i as usize) < 9 * 5 + 24));
+    }
+
+    #[test]
+    fn test_neighbour_meshes_match() {
+        let world_gen = WorldGen::new(1., Some(2));
+        let settings = MeshSettings::default().set_size([4, 4]);
+        let left = TerrainMesh::generate(&world_gen, &settings);
+        let right = TerrainMesh::generate(&world_gen, &settings.set_origin([4, 0]));
+        // The last column of the left mesh is the first one of the right mesh
+        assert_eq!(left.positions[20..25], right.positions[0..5]);
+        assert_eq!(left.normals[20..25], right.normals[0..5]);
+    }
+
+    #[test]
+    fn test_write() {
+        let mesh = small_mesh(Some(1.));
+        let mut glb = vec![];
+        mesh.write_glb(&mut glb).unwrap();
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(
+            u32::from_le_bytes([glb[8], glb[9], glb[10], glb[11]]) as usize,
+            glb.len()
+        );
+        assert_eq!(glb.len() % 4, 0);
+
+        let mut obj = vec![];
+        mesh.write_obj(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert_eq!(
+            obj.lines().filter(|l| l.starts_with("v ")).count(),
+            mesh.positions.len()
+        );
+        assert_eq!(
+            obj.lines().filter(|l| l.starts_with("f ")).count(),
+            mesh.indices.len() / 3
+        );
+    }
+}
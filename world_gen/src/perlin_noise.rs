@@ -60,8 +60,14 @@ impl PerlinNoiseGenerator {
     fn fade(t: f64) -> f64 {
         6.0f64.mul_add(t, -15.).mul_add(t, 10.) * t * t * t
     }
+    /// Derivative of [`Self::fade`]
+    fn fade_derivative(t: f64) -> f64 {
+        30. * t * t * (t - 1.) * (t - 1.)
+    }
+    /// Position in its lattice square and the vectors of the bottom left, top left, bottom right
+    /// and top right corners
     #[allow(clippy::similar_names)]
-    fn perlin(&self, pos: (f64, f64)) -> f64 {
+    fn lattice(&self, pos: (f64, f64)) -> ((f64, f64), [Vector2; 4]) {
         let (x, y) = (pos.0 + POS_OFFSET, pos.1 + POS_OFFSET);
         #[allow(clippy::cast_possible_truncation)]
         let (nx, ny) = (
@@ -70,27 +76,62 @@ impl PerlinNoiseGenerator {
         );
         let (fx, fy) = (x - x.floor(), y - y.floor());
 
-        let tr = Vector2(fx - 1.0, fy - 1.0);
-        let tl = Vector2(fx, fy - 1.0);
-        let br = Vector2(fx - 1.0, fy);
-        let bl = Vector2(fx, fy);
-
         let size = self.permutations.len();
         let v_tr = self.permutations[(self.permutations[(nx + 1) % size] + (ny + 1) % size) % size];
         let v_tl = self.permutations[(self.permutations[nx % size] + (ny + 1) % size) % size];
         let v_br = self.permutations[(self.permutations[(nx + 1) % size] + ny % size) % size];
         let v_bl = self.permutations[(self.permutations[nx % size] + ny % size) % size];
 
-        let d_tr = tr.dot_product(&Self::constant_vector(v_tr));
-        let d_tl = tl.dot_product(&Self::constant_vector(v_tl));
-        let d_br = br.dot_product(&Self::constant_vector(v_br));
-        let d_bl = bl.dot_product(&Self::constant_vector(v_bl));
+        (
+            (fx, fy),
+            [v_bl, v_tl, v_br, v_tr].map(Self::constant_vector),
+        )
+    }
+    fn perlin(&self, pos: (f64, f64)) -> f64 {
+        let ((fx, fy), [g_bl, g_tl, g_br, g_tr]) = self.lattice(pos);
+
+        let tr = Vector2(fx - 1.0, fy - 1.0);
+        let tl = Vector2(fx, fy - 1.0);
+        let br = Vector2(fx - 1.0, fy);
+        let bl = Vector2(fx, fy);
+
+        let d_tr = tr.dot_product(&g_tr);
+        let d_tl = tl.dot_product(&g_tl);
+        let d_br = br.dot_product(&g_br);
+        let d_bl = bl.dot_product(&g_bl);
 
         let u = Self::fade(fx);
         let v = Self::fade(fy);
 
         Self::lerp(u, Self::lerp(v, d_bl, d_tl), Self::lerp(v, d_br, d_tr))
     }
+    /// Noise of [`Self::perlin`] with its derivatives along x and y
+    fn perlin_with_gradient(&self, pos: (f64, f64)) -> (f64, (f64, f64)) {
+        let ((fx, fy), [g_bl, g_tl, g_br, g_tr]) = self.lattice(pos);
+
+        let d_tr = Vector2(fx - 1.0, fy - 1.0).dot_product(&g_tr);
+        let d_tl = Vector2(fx, fy - 1.0).dot_product(&g_tl);
+        let d_br = Vector2(fx - 1.0, fy).dot_product(&g_br);
+        let d_bl = Vector2(fx, fy).dot_product(&g_bl);
+
+        let (u, du) = (Self::fade(fx), Self::fade_derivative(fx));
+        let (v, dv) = (Self::fade(fy), Self::fade_derivative(fy));
+        // Left and right edges, and their derivatives
+        let left = Self::lerp(v, d_bl, d_tl);
+        let right = Self::lerp(v, d_br, d_tr);
+        let left_dx = Self::lerp(v, g_bl.0, g_tl.0);
+        let right_dx = Self::lerp(v, g_br.0, g_tr.0);
+        let left_dy = dv.mul_add(d_tl - d_bl, Self::lerp(v, g_bl.1, g_tl.1));
+        let right_dy = dv.mul_add(d_tr - d_br, Self::lerp(v, g_br.1, g_tr.1));
+
+        (
+            Self::lerp(u, left, right),
+            (
+                du.mul_add(right - left, Self::lerp(u, left_dx, right_dx)),
+                Self::lerp(u, left_dy, right_dy),
+            ),
+        )
+    }
     fn fractal_brownian_motion(&self, pos: (f64, f64), scale: f64) -> f64 {
         let mut result = 0.0;
         for oct in 0..self.octaves {
@@ -100,6 +141,26 @@ impl PerlinNoiseGenerator {
         }
         result
     }
+    /// Sum of the octaves like [`Self::fractal_brownian_motion`], each derivative being scaled by
+    /// the frequency of its octave
+    fn fractal_brownian_motion_with_gradient(
+        &self,
+        pos: (f64, f64),
+        scale: f64,
+    ) -> (f64, (f64, f64)) {
+        let mut result = (0.0, (0.0, 0.0));
+        for oct in 0..self.octaves {
+            let freq = self.lacunarity.powi(oct.try_into().unwrap());
+            let amplitude = self.persistence.powi(oct.try_into().unwrap());
+            let (value, (dx, dy)) =
+                self.perlin_with_gradient((pos.0 * freq / scale, pos.1 * freq / scale));
+            let slope = amplitude * freq / scale;
+            result.0 += amplitude * value;
+            result.1 .0 += slope * dx;
+            result.1 .1 += slope * dy;
+        }
+        result
+    }
     #[must_use]
     /// Generate noise from coordinates.
     pub fn noise(&self, pos: (f64, f64), scale: f64) -> f64 {
@@ -109,4 +170,43 @@ impl PerlinNoiseGenerator {
             self.fractal_brownian_motion(pos, scale)
         }
     }
+    #[must_use]
+    /// Noise of [`Self::noise`] with its analytic derivatives along x and y
+    pub fn noise_with_gradient(&self, pos: (f64, f64), scale: f64) -> (f64, (f64, f64)) {
+        if self.octaves == 0 {
+            self.perlin_with_gradient(pos)
+        } else {
+            self.fractal_brownian_motion_with_gradient(pos, scale)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PerlinNoiseGenerator;
+
+    #[test]
+    fn test_gradient() {
+        let step = 1e-6;
+        for noise in [
+            PerlinNoiseGenerator::new(3),
+            PerlinNoiseGenerator::new(5)
+                .set_lacunarity(1.7)
+                .set_persistence(0.6)
+                .set_octaves(8),
+        ] {
+            for pos in [(0.25, 0.5), (-13.7, 4.1), (250.3, -77.9), (3., -2.)] {
+                let (value, (dx, dy)) = noise.noise_with_gradient(pos, 20.);
+                assert_eq!(value, noise.noise(pos, 20.));
+                let finite_dx = (noise.noise((pos.0 + step, pos.1), 20.)
+                    - noise.noise((pos.0 - step, pos.1), 20.))
+                    / (2. * step);
+                let finite_dy = (noise.noise((pos.0, pos.1 + step), 20.)
+                    - noise.noise((pos.0, pos.1 - step), 20.))
+                    / (2. * step);
+                assert!((dx - finite_dx).abs() < 1e-6, "{dx} {finite_dx}");
+                assert!((dy - finite_dy).abs() < 1e-6, "{dy} {finite_dy}");
+            }
+        }
+    }
 }
@@ -0,0 +1,396 @@
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+
+use crate::city_generation::{Building, BuildingType, CityGenerator};
+
+const FIRST_NAMES: [&str; 32] = [
+    "Alaric", "Bertrand", "Cedric", "Doran", "Edmund", "Fendrel", "Gareth", "Hugo", "Isold",
+    "Jehan", "Kaspar", "Leofric", "Merek", "Niall", "Osric", "Percival", "Adela", "Beatrix",
+    "Cecily", "Daria", "Elinor", "Fiora", "Gisela", "Helena", "Ida", "Joan", "Katrin", "Liesel",
+    "Maude", "Nesta", "Odile", "Rosamund",
+];
+const SURNAME_PREFIXES: [&str; 16] = [
+    "Ash", "Black", "Brook", "Cold", "Dun", "Fair", "Green", "Hawk", "Iron", "Long", "Mill", "Oak",
+    "Red", "Stone", "Thorn", "White",
+];
+const SURNAME_SUFFIXES: [&str; 12] = [
+    "wood", "ford", "well", "smith", "ley", "wick", "ton", "field", "brook", "hill", "gate",
+    "wright",
+];
+
+/// Age from which an NPC works
+const ADULT_AGE: u32 = 16;
+/// Age from which an NPC stops working
+const RETIREMENT_AGE: u32 = 65;
+/// Age from which a child goes to the temple to study
+const SCHOOL_AGE: u32 = 6;
+/// Floor area needed per inhabitant of a house
+const AREA_PER_INHABITANT: i32 = 60;
+/// Floor area needed per worker of a workplace
+const AREA_PER_WORKER: i32 = 80;
+
+/// Job of an NPC, matched to the type of the building they work in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Profession {
+    Farmer,
+    Merchant,
+    Craftsman,
+    Innkeeper,
+    Porter,
+    Mayor,
+    Clerk,
+    Trader,
+    Priest,
+    Guard,
+    Guildmaster,
+    /// Adult without a workplace, works wherever they can
+    Laborer,
+    Child,
+    Retired,
+}
+
+impl Profession {
+    /// Profession of the first worker of a building and of the following ones
+    const fn for_building(building_type: BuildingType, first: bool) -> Self {
+        match building_type {
+            BuildingType::House => Self::Laborer,
+            BuildingType::Farm => Self::Farmer,
+            BuildingType::Shop => Self::Merchant,
+            BuildingType::Workshop => Self::Craftsman,
+            BuildingType::Tavern => Self::Innkeeper,
+            BuildingType::Warehouse => Self::Porter,
+            BuildingType::TownHall if first => Self::Mayor,
+            BuildingType::TownHall => Self::Clerk,
+            BuildingType::Market => Self::Trader,
+            BuildingType::Temple => Self::Priest,
+            BuildingType::Barracks => Self::Guard,
+            BuildingType::Guildhall if first => Self::Guildmaster,
+            BuildingType::Guildhall => Self::Craftsman,
+        }
+    }
+}
+
+/// What an NPC is doing during a slot of their day
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Activity {
+    Sleep,
+    Eat,
+    Work,
+    Study,
+    Worship,
+    Socialize,
+    Leisure,
+}
+
+/// Slot of the daily schedule, hours go from 0 to 24
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ScheduleEntry {
+    pub start_hour: u8,
+    pub end_hour: u8,
+    pub activity: Activity,
+    /// ID of the building where the activity takes place
+    pub building: usize,
+}
+
+/// Inhabitant of the city
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Npc {
+    /// Unique identifier
+    pub id: usize,
+    pub first_name: String,
+    pub surname: String,
+    pub age: u32,
+    /// ID of the family of the NPC
+    pub family: usize,
+    pub profession: Profession,
+    /// ID of the building the NPC lives in
+    pub home: usize,
+    /// ID of the building the NPC works or studies in
+    pub work: Option<usize>,
+    /// What the NPC does during a day, covers the 24 hours
+    pub schedule: Vec<ScheduleEntry>,
+}
+
+/// NPCs sharing a surname and a home
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Family {
+    /// Unique identifier
+    pub id: usize,
+    pub surname: String,
+    /// ID of the building the family lives in
+    pub home: usize,
+    /// IDs of the members of the family
+    pub members: Vec<usize>,
+}
+
+/// Population of a city
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Population {
+    pub npcs: Vec<Npc>,
+    pub families: Vec<Family>,
+}
+
+/// Free job in a workplace
+struct JobSlot {
+    building: usize,
+    building_type: BuildingType,
+    first: bool,
+}
+
+/// Random population generator for a city
+pub struct PopulationGenerator {
+    rng: ChaCha8Rng,
+}
+
+impl PopulationGenerator {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Populate a generated city, the same seed and city always give the same population
+    pub fn generate(&mut self, city: &CityGenerator) -> Population {
+        let mut buildings = city.buildings.values().collect::<Vec<&Building>>();
+        buildings.sort_by_key(|b| b.id);
+
+        let temple = buildings
+            .iter()
+            .find(|b| b.building_type == BuildingType::Temple)
+            .map(|b| b.id);
+        let market = buildings
+            .iter()
+            .find(|b| b.building_type == BuildingType::Market)
+            .map(|b| b.id);
+        let taverns = buildings
+            .iter()
+            .filter(|b| b.building_type == BuildingType::Tavern)
+            .map(|b| b.id)
+            .collect::<Vec<_>>();
+
+        let mut population = Population::default();
+        // Farmers live on their farm, everyone else lives in houses
+        for home in buildings
+            .iter()
+            .filter(|b| matches!(b.building_type, BuildingType::House | BuildingType::Farm))
+        {
+            self.generate_family(&mut population, home);
+        }
+
+        let mut jobs = self.job_slots(&buildings);
+        for npc in &mut population.npcs {
+            if npc.age < ADULT_AGE {
+                npc.profession = Profession::Child;
+                npc.work = if npc.age >= SCHOOL_AGE { temple } else { None };
+            } else if npc.age >= RETIREMENT_AGE {
+                npc.profession = Profession::Retired;
+            } else if let Some(slot) = jobs.pop() {
+                npc.profession = Profession::for_building(slot.building_type, slot.first);
+                npc.work = Some(slot.building);
+            } else {
+                npc.profession = Profession::Laborer;
+                npc.work = market;
+            }
+            let tavern = taverns.choose(&mut self.rng).copied();
+            npc.schedule = Self::schedule(npc, tavern, temple);
+        }
+        population
+    }
+
+    /// Create a family living in `home`
+    fn generate_family(&mut self, population: &mut Population, home: &Building) {
+        let family_id = population.families.len();
+        let surname = format!(
+            "{}{}",
+            SURNAME_PREFIXES.choose(&mut self.rng).unwrap(),
+            SURNAME_SUFFIXES.choose(&mut self.rng).unwrap()
+        );
+        let capacity = (home.footprint.area() / AREA_PER_INHABITANT).clamp(1, 8);
+        let size = self.rng.gen_range(1..=capacity);
+
+        let parent_age = self.rng.gen_range(ADULT_AGE + 4..60);
+        let mut ages = vec![parent_age];
+        if size > 1 {
+            ages.push((parent_age + self.rng.gen_range(0..6)).saturating_sub(3));
+        }
+        for _ in 2..size {
+            if self.rng.gen_bool(0.2) {
+                // Grandparent
+                ages.push(self.rng.gen_range(parent_age + 18..parent_age + 40).min(95));
+            } else {
+                ages.push(self.rng.gen_range(0..(parent_age - ADULT_AGE).max(1)));
+            }
+        }
+
+        let mut members = Vec::with_capacity(ages.len());
+        for age in ages {
+            let id = population.npcs.len();
+            members.push(id);
+            population.npcs.push(Npc {
+                id,
+                first_name: (*FIRST_NAMES.choose(&mut self.rng).unwrap()).to_string(),
+                surname: surname.clone(),
+                age,
+                family: family_id,
+                profession: Profession::Laborer,
+                home: home.id,
+                work: None,
+                schedule: vec![],
+            });
+        }
+        population.families.push(Family {
+            id: family_id,
+            surname,
+            home: home.id,
+            members,
+        });
+    }
+
+    /// List the jobs offered by every workplace, shuffled so that they are given out randomly
+    fn job_slots(&mut self, buildings: &[&Building]) -> Vec<JobSlot> {
+        let mut slots = vec![];
+        for building in buildings
+            .iter()
+            .filter(|b| b.building_type != BuildingType::House)
+        {
+            let workers = (building.footprint.area() / AREA_PER_WORKER).clamp(1, 12);
+            for i in 0..workers {
+                slots.push(JobSlot {
+                    building: building.id,
+                    building_type: building.building_type,
+                    first: i == 0,
+                });
+            }
+        }
+        slots.shuffle(&mut self.rng);
+        // Heads of important buildings are given first
+        slots.sort_by_key(|slot| slot.first);
+        slots
+    }
+
+    /// Build the daily schedule of an NPC
+    fn schedule(npc: &Npc, tavern: Option<usize>, temple: Option<usize>) -> Vec<ScheduleEntry> {
+        let entry = |start_hour, end_hour, activity, building| ScheduleEntry {
+            start_hour,
+            end_hour,
+            activity,
+            building,
+        };
+        let home = npc.home;
+        let work = npc.work.unwrap_or(home);
+        let evening = |start: u8| match (tavern, npc.age >= ADULT_AGE) {
+            (Some(tavern), true) => vec![
+                entry(start, 21, Activity::Socialize, tavern),
+                entry(21, 24, Activity::Sleep, home),
+            ],
+            _ => vec![
+                entry(start, 21, Activity::Leisure, home),
+                entry(21, 24, Activity::Sleep, home),
+            ],
+        };
+
+        let mut schedule = match npc.profession {
+            // Night shift
+            Profession::Guard if npc.id.is_multiple_of(2) => {
+                return vec![
+                    entry(0, 6, Activity::Work, work),
+                    entry(6, 14, Activity::Sleep, home),
+                    entry(14, 15, Activity::Eat, home),
+                    entry(15, 20, Activity::Leisure, home),
+                    entry(20, 22, Activity::Eat, home),
+                    entry(22, 24, Activity::Work, work),
+                ]
+            }
+            Profession::Innkeeper => {
+                return vec![
+                    entry(0, 1, Activity::Work, work),
+                    entry(1, 9, Activity::Sleep, home),
+                    entry(9, 10, Activity::Eat, home),
+                    entry(10, 24, Activity::Work, work),
+                ]
+            }
+            Profession::Farmer => vec![
+                entry(0, 5, Activity::Sleep, home),
+                entry(5, 6, Activity::Eat, home),
+                entry(6, 18, Activity::Work, work),
+                entry(18, 19, Activity::Eat, home),
+            ],
+            Profession::Priest => vec![
+                entry(0, 6, Activity::Sleep, home),
+                entry(6, 7, Activity::Eat, home),
+                entry(7, 19, Activity::Work, work),
+                entry(19, 20, Activity::Eat, home),
+            ],
+            Profession::Child => vec![
+                entry(0, 7, Activity::Sleep, home),
+                entry(7, 8, Activity::Eat, home),
+                entry(
+                    8,
+                    15,
+                    if npc.work.is_some() {
+                        Activity::Study
+                    } else {
+                        Activity::Leisure
+                    },
+                    work,
+                ),
+                entry(15, 19, Activity::Leisure, home),
+                entry(19, 20, Activity::Eat, home),
+            ],
+            Profession::Retired => vec![
+                entry(0, 7, Activity::Sleep, home),
+                entry(7, 8, Activity::Eat, home),
+                entry(8, 10, Activity::Worship, temple.unwrap_or(home)),
+                entry(10, 19, Activity::Leisure, home),
+                entry(19, 20, Activity::Eat, home),
+            ],
+            _ => vec![
+                entry(0, 6, Activity::Sleep, home),
+                entry(6, 7, Activity::Eat, home),
+                entry(7, 12, Activity::Work, work),
+                entry(12, 13, Activity::Eat, work),
+                entry(13, 18, Activity::Work, work),
+                entry(18, 19, Activity::Eat, home),
+            ],
+        };
+        if npc.profession == Profession::Child {
+            schedule.push(entry(20, 24, Activity::Sleep, home));
+        } else {
+            let start = schedule.last().map_or(0, |e| e.end_hour);
+            schedule.extend(evening(start));
+        }
+        schedule
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::PopulationGenerator;
+    use crate::city_generation::{CityConfig, CityGenerator};
+
+    #[test]
+    fn test_same_seed_same_population() {
+        let mut city_gen = CityGenerator::new(
+            CityConfig::default()
+                .set_seed(1)
+                .set_buildings(50)
+                .set_width_bound(10..30)
+                .set_height_bound(10..30),
+        );
+        city_gen.generate().unwrap();
+
+        let population1 = PopulationGenerator::new(1).generate(&city_gen);
+        let population2 = PopulationGenerator::new(1).generate(&city_gen);
+        assert_eq!(population1, population2);
+
+        for npc in &population1.npcs {
+            assert_eq!(npc.schedule.first().unwrap().start_hour, 0);
+            assert_eq!(npc.schedule.last().unwrap().end_hour, 24);
+            assert!(npc
+                .schedule
+                .windows(2)
+                .all(|w| w[0].end_hour == w[1].start_hour));
+        }
+    }
+}
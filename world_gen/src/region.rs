@@ -0,0 +1,128 @@
+//! Rectangles of cells to generate
+
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+/// Rectangle of cells, `x` and `y` being its south west corner
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Region {
+    pub x: isize,
+    pub y: isize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    #[must_use]
+    pub const fn new(x: isize, y: isize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Region of this size centered on the origin of the world
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)]
+    pub const fn centered(width: usize, height: usize) -> Self {
+        Self::new(-(width as isize) / 2, -(height as isize) / 2, width, height)
+    }
+
+    /// Coordinates of the south west cell
+    #[must_use]
+    pub const fn origin(self) -> (isize, isize) {
+        (self.x, self.y)
+    }
+
+    #[must_use]
+    pub const fn size(self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    #[must_use]
+    pub const fn len(self) -> usize {
+        self.width * self.height
+    }
+
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cell in the middle of the region
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)]
+    pub const fn center(self) -> (isize, isize) {
+        (
+            self.x + (self.width / 2) as isize,
+            self.y + (self.height / 2) as isize,
+        )
+    }
+
+    /// Coordinates of the cell at `index`, row by row with the northmost row first like images
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)]
+    pub const fn cell(self, index: usize) -> (isize, isize) {
+        let (column, row) = (index % self.width, index / self.width);
+        (
+            self.x + column as isize,
+            self.y + (self.height - 1 - row) as isize,
+        )
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parse a region written as `x,y,width,height`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(',').map(str::trim).collect::<Vec<_>>();
+        let [x, y, width, height] = parts[..] else {
+            return Err(format!("expected x,y,width,height, got {s}"));
+        };
+        let position = |n: &str| n.parse::<isize>().map_err(|e| format!("{n}: {e}"));
+        let size = |n: &str| match n.parse::<usize>() {
+            Ok(0) => Err("the size of the region can't be 0".to_string()),
+            Ok(size) => Ok(size),
+            Err(e) => Err(format!("{n}: {e}")),
+        };
+        Ok(Self::new(
+            position(x)?,
+            position(y)?,
+            size(width)?,
+            size(height)?,
+        ))
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Region;
+
+    #[test]
+    fn test_parse_region() {
+        let region = "-10, 5,20,8".parse::<Region>().unwrap();
+        assert_eq!(region, Region::new(-10, 5, 20, 8));
+        assert_eq!(region.to_string().parse(), Ok(region));
+        assert!("1,2,3".parse::<Region>().is_err());
+        assert!("1,2,0,3".parse::<Region>().is_err());
+        assert!("1,2,-3,3".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_cells() {
+        let region = Region::new(-10, 5, 20, 8);
+        assert_eq!(region.cell(0), (-10, 12));
+        assert_eq!(region.cell(21), (-9, 11));
+        assert_eq!(region.cell(region.len() - 1), (9, 5));
+        assert_eq!(Region::centered(20, 8).center(), (0, 0));
+    }
+}
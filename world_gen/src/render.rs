@@ -0,0 +1,322 @@
+//! Raster rendering of the generated world and cities
+
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use image::{ImageBuffer, ImageResult, Rgb, RgbImage};
+use rayon::prelude::*;
+
+use crate::{
+    cell::Cell,
+    city_generation::CityGenerator,
+    image_utils::{draw_polygon, draw_rect},
+    region::Region,
+    WorldGen,
+};
+
+/// Rows of cells generated at once when streaming images
+const BAND_HEIGHT: usize = 64;
+
+/// Color of a cell from its coordinates and its content
+pub type CellColor<'a> = &'a (dyn Fn((isize, isize), &Cell) -> Rgb<u8> + Sync);
+
+/// Noise layer used to pick the biomes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Layer {
+    Temperature,
+    Moisture,
+    Continentalness,
+    Erosion,
+}
+
+impl Layer {
+    pub const ALL: [Self; 4] = [
+        Self::Temperature,
+        Self::Moisture,
+        Self::Continentalness,
+        Self::Erosion,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Temperature => "temperature",
+            Self::Moisture => "moisture",
+            Self::Continentalness => "continentalness",
+            Self::Erosion => "erosion",
+        }
+    }
+
+    /// Value of the layer in the cell, between -1 and 1
+    #[must_use]
+    pub const fn value(self, cell: &Cell) -> f64 {
+        match self {
+            Self::Temperature => cell.temp,
+            Self::Moisture => cell.moisture,
+            Self::Continentalness => cell.continentalness,
+            Self::Erosion => cell.erosion,
+        }
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn color(self, cell: &Cell) -> Rgb<u8> {
+        let value = (255. * (self.value(cell) + 1.) / 2.) as u8;
+        match self {
+            // red = warm or dry, blue = cold or wet
+            Self::Temperature | Self::Moisture => Rgb([value, 0, 255 - value]),
+            // white = ocean or high erosion, black = terrain or low erosion
+            Self::Continentalness | Self::Erosion => Rgb([value; 3]),
+        }
+    }
+}
+
+/// Cells of the region, row by row with the northmost row first
+#[must_use]
+pub fn generate_region(world_gen: &WorldGen, region: Region) -> Vec<Cell> {
+    (0..region.len())
+        .into_par_iter()
+        .map(|i| world_gen.generate_cell(region.cell(i)))
+        .collect()
+}
+
+/// Image of the cells of [`generate_region`], north up
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn cells_image(cells: &[Cell], region: Region, color: impl Fn(&Cell) -> Rgb<u8>) -> RgbImage {
+    let pixels = cells.iter().flat_map(|cell| color(cell).0).collect();
+    ImageBuffer::from_raw(region.width as u32, region.height as u32, pixels)
+        .expect("one cell per pixel")
+}
+
+/// Biome map of the cells of [`generate_region`], north up
+#[must_use]
+pub fn biome_image(cells: &[Cell], region: Region) -> RgbImage {
+    cells_image(cells, region, |cell| Rgb(cell.biome.color()))
+}
+
+/// Map of a noise layer of the cells of [`generate_region`], north up
+#[must_use]
+pub fn layer_image(cells: &[Cell], region: Region, layer: Layer) -> RgbImage {
+    cells_image(cells, region, |cell| layer.color(cell))
+}
+
+/// Render the region as PNG images, one per output, without holding all its cells in memory
+///
+/// The cells are generated a band of rows at a time, which is colored and written to every
+/// output before the next one, north first. Each cell is a square of `pixels_per_cell` pixels.
+/// `progress` is called with the number of rows of cells done after each band.
+///
+/// # Errors
+///
+/// Fails if an output can't be written or if the image is too large for a PNG.
+#[allow(clippy::cast_possible_wrap)]
+pub fn stream_region_png<W: Write>(
+    world_gen: &WorldGen,
+    region: Region,
+    pixels_per_cell: u32,
+    outputs: Vec<(W, CellColor)>,
+    mut progress: impl FnMut(usize),
+) -> ImageResult<()> {
+    let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "image too large for a PNG");
+    let zoom = pixels_per_cell.max(1);
+    let size = |cells: usize| {
+        u32::try_from(cells)
+            .ok()
+            .and_then(|cells| cells.checked_mul(zoom))
+            .ok_or_else(too_large)
+    };
+    let (width, height) = (size(region.width)?, size(region.height)?);
+    let mut encoders = vec![];
+    for (writer, color) in outputs {
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoders.push((encoder.write_header().map_err(io::Error::from)?, color));
+    }
+    let mut writers = vec![];
+    for (encoder, color) in &mut encoders {
+        writers.push((encoder.stream_writer().map_err(io::Error::from)?, color));
+    }
+
+    let zoom = zoom as usize;
+    let mut row_pixels = Vec::with_capacity(width as usize * 3);
+    for start in (0..region.height).step_by(BAND_HEIGHT) {
+        let rows = BAND_HEIGHT.min(region.height - start);
+        // Rows of the band, the northmost one being `start` rows below the north of the region
+        let band = Region::new(
+            region.x,
+            region.y + (region.height - start - rows) as isize,
+            region.width,
+            rows,
+        );
+        let cells = generate_region(world_gen, band);
+        for (writer, color) in &mut writers {
+            for (row, row_cells) in cells.chunks(region.width).enumerate() {
+                row_pixels.clear();
+                for (column, cell) in row_cells.iter().enumerate() {
+                    let pixel = color(band.cell(row * region.width + column), cell).0;
+                    for _ in 0..zoom {
+                        row_pixels.extend_from_slice(&pixel);
+                    }
+                }
+                for _ in 0..zoom {
+                    writer.write_all(&row_pixels)?;
+                }
+            }
+        }
+        progress(start + rows);
+    }
+    for (writer, _) in writers {
+        writer.finish().map_err(io::Error::from)?;
+    }
+    for (encoder, _) in encoders {
+        encoder.finish().map_err(io::Error::from)?;
+    }
+    Ok(())
+}
+
+/// Image of the city, one pixel per unit with a margin on the right and the bottom
+///
+/// Buildings are coloured from red to blue by ID, green if they are important.
+#[must_use]
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub fn city_image(city_gen: &CityGenerator) -> RgbImage {
+    let buildings = city_gen.config().buildings;
+    let mut img = ImageBuffer::new(
+        10 + (city_gen.max_x - city_gen.min_x) as u32,
+        10 + (city_gen.max_y - city_gen.min_y) as u32,
+    );
+
+    // roads
+    for road in &city_gen.roads {
+        for (x, y) in road {
+            img.put_pixel(
+                *x as u32 - city_gen.min_x as u32,
+                *y as u32 - city_gen.min_y as u32,
+                Rgb([139, 69, 19]),
+            );
+        }
+    }
+    if let Some(wall) = &city_gen.wall {
+        for (x, y) in &wall.cells {
+            img.put_pixel(
+                *x as u32 - city_gen.min_x as u32,
+                *y as u32 - city_gen.min_y as u32,
+                Rgb([128, 128, 128]),
+            );
+        }
+        for (x, y) in &wall.towers {
+            draw_rect(
+                &mut img,
+                (
+                    *x as u32 - city_gen.min_x as u32 - 2,
+                    *y as u32 - city_gen.min_y as u32 - 2,
+                ),
+                4,
+                4,
+                Rgb([200, 200, 200]),
+            );
+        }
+        for (x, y) in &wall.gates {
+            img.put_pixel(
+                *x as u32 - city_gen.min_x as u32,
+                *y as u32 - city_gen.min_y as u32,
+                Rgb([255, 255, 0]),
+            );
+        }
+    }
+    for building in city_gen.buildings.values() {
+        let outline = building
+            .footprint
+            .vertices
+            .iter()
+            .map(|(x, y)| (x - city_gen.min_x, y - city_gen.min_y))
+            .collect::<Vec<_>>();
+        draw_polygon(
+            &mut img,
+            &outline,
+            // color based on id, the less the more red
+            Rgb([
+                255 - (building.id as f32 / buildings as f32 * 255.0) as u8,
+                if building.is_important { 255 } else { 0 },
+                (building.id as f32 / buildings as f32 * 255.0) as u8,
+            ]),
+        );
+        for door in &building.doors {
+            draw_rect(
+                &mut img,
+                (
+                    door.0 as u32 - city_gen.min_x as u32,
+                    door.1 as u32 - city_gen.min_y as u32,
+                ),
+                0,
+                0,
+                Rgb([255, 0, 0]),
+            );
+        }
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::{biome_image, generate_region, layer_image, stream_region_png, Layer};
+    use crate::{cell::Cell, region::Region, WorldGen};
+
+    #[test]
+    fn test_region_images() {
+        let world_gen = WorldGen::new(1., Some(4));
+        let region = Region::new(-10, 5, 20, 8);
+        let cells = generate_region(&world_gen, region);
+        assert_eq!(cells.len(), 160);
+
+        let image = biome_image(&cells, region);
+        assert_eq!(image.dimensions(), (20, 8));
+        // North up: the top left pixel is the north west cell
+        let north_west = world_gen.generate_cell((-10, 12));
+        assert_eq!(image.get_pixel(0, 0).0, north_west.biome.color());
+        let image = layer_image(&cells, region, Layer::Erosion);
+        assert_eq!(*image.get_pixel(0, 0), Layer::Erosion.color(&north_west));
+    }
+
+    #[test]
+    fn test_stream_region_png() {
+        let world_gen = WorldGen::new(1., Some(4));
+        // Taller than a band
+        let region = Region::new(-3, -40, 6, 100);
+        let cells = generate_region(&world_gen, region);
+        let expected = biome_image(&cells, region);
+
+        let (mut biomes, mut erosion) = (vec![], vec![]);
+        let biome_color = |_, cell: &Cell| Rgb(cell.biome.color());
+        let erosion_color = |_, cell: &Cell| Layer::Erosion.color(cell);
+        let mut rows_done = 0;
+        stream_region_png(
+            &world_gen,
+            region,
+            2,
+            vec![(&mut biomes, &biome_color), (&mut erosion, &erosion_color)],
+            |rows| rows_done = rows,
+        )
+        .unwrap();
+        assert_eq!(rows_done, 100);
+
+        let biomes = image::load_from_memory(&biomes).unwrap().into_rgb8();
+        assert_eq!(biomes.dimensions(), (12, 200));
+        for (x, y, pixel) in expected.enumerate_pixels() {
+            assert_eq!(biomes.get_pixel(x * 2 + 1, y * 2 + 1), pixel);
+        }
+        let erosion = image::load_from_memory(&erosion).unwrap().into_rgb8();
+        assert_eq!(
+            *erosion.get_pixel(11, 199),
+            Layer::Erosion.color(&world_gen.generate_cell((2, -40)))
+        );
+    }
+}
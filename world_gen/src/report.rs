@@ -0,0 +1,292 @@
+//! Statistics of a region of the world, to compare seeds before picking one
+
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{collections::VecDeque, fmt};
+
+use crate::{biome::BiomeType, region::Region, render::Layer, WorldGen};
+
+/// Range of the heights in the histogram, the heights outside of it are in the first or last bin
+pub const HEIGHT_RANGE: (f64, f64) = (-1.8, 1.4);
+/// Number of bins of the height histogram
+pub const HEIGHT_BINS: usize = 32;
+
+/// Share of the region covered by a biome
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BiomeArea {
+    pub biome: String,
+    pub fraction: f64,
+}
+
+/// Range and average of a value over the region
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LayerStatistics {
+    pub name: &'static str,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// Number of samples in bins of equal size between the bounds of [`HEIGHT_RANGE`]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HeightHistogram {
+    pub min: f64,
+    pub bin_size: f64,
+    pub counts: Vec<usize>,
+}
+
+/// Statistics of a region, sampled every `step` cells in both directions
+///
+/// Areas are in cells, counting `step²` cells per sample. Land masses are groups of land
+/// samples connected by their sides, cut at the borders of the region.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WorldReport {
+    pub seed: u64,
+    pub region: Region,
+    pub step: usize,
+    pub samples: usize,
+    /// Most common biome first
+    pub biomes: Vec<BiomeArea>,
+    pub land_fraction: f64,
+    pub water_fraction: f64,
+    /// Area of the largest land mass
+    pub largest_landmass: usize,
+    /// Number of land masses other than the largest one
+    pub islands: usize,
+    pub height_histogram: HeightHistogram,
+    /// Noise layers then height
+    pub layers: Vec<LayerStatistics>,
+}
+
+impl WorldReport {
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn generate(world_gen: &WorldGen, region: Region, step: usize) -> Self {
+        let step = step.max(1);
+        let (columns, rows) = (region.width.div_ceil(step), region.height.div_ceil(step));
+        // Samples on the northmost row first, like the cells of a region
+        let cells = (0..columns * rows)
+            .into_par_iter()
+            .map(|i| {
+                let (column, row) = (i % columns, i / columns);
+                world_gen.generate_cell((
+                    region.x + (column * step) as isize,
+                    region.y + (region.height - 1 - row * step) as isize,
+                ))
+            })
+            .collect::<Vec<_>>();
+        let samples = cells.len();
+
+        let mut biome_counts = [0; BiomeType::ALL.len()];
+        for cell in &cells {
+            biome_counts[cell.biome as usize] += 1;
+        }
+        let mut biomes = BiomeType::ALL
+            .iter()
+            .zip(biome_counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(biome, count)| BiomeArea {
+                biome: format!("{biome:?}"),
+                fraction: count as f64 / samples as f64,
+            })
+            .collect::<Vec<_>>();
+        biomes.sort_by(|a, b| b.fraction.total_cmp(&a.fraction));
+
+        let land = cells
+            .iter()
+            .map(|c| !c.biome.is_water())
+            .collect::<Vec<_>>();
+        let land_fraction = land.iter().filter(|l| **l).count() as f64 / samples as f64;
+        let mut landmasses = landmass_sizes(&land, columns);
+        landmasses.sort_unstable_by(|a, b| b.cmp(a));
+
+        let (min, max) = HEIGHT_RANGE;
+        let bin_size = (max - min) / HEIGHT_BINS as f64;
+        let mut counts = vec![0; HEIGHT_BINS];
+        for cell in &cells {
+            let bin = ((cell.height - min) / bin_size).floor().max(0.) as usize;
+            counts[bin.min(HEIGHT_BINS - 1)] += 1;
+        }
+
+        let statistics = |name, value: &dyn Fn(usize) -> f64| {
+            let (min, max, sum) = (0..samples).fold(
+                (f64::INFINITY, f64::NEG_INFINITY, 0.),
+                |(min, max, sum), i| {
+                    let value = value(i);
+                    (min.min(value), max.max(value), sum + value)
+                },
+            );
+            LayerStatistics {
+                name,
+                min,
+                max,
+                mean: sum / samples as f64,
+            }
+        };
+        let mut layers = Layer::ALL
+            .iter()
+            .map(|layer| statistics(layer.name(), &|i| layer.value(&cells[i])))
+            .collect::<Vec<_>>();
+        layers.push(statistics("height", &|i| cells[i].height));
+
+        Self {
+            seed: world_gen.seed,
+            region,
+            step,
+            samples,
+            biomes,
+            land_fraction,
+            water_fraction: 1. - land_fraction,
+            largest_landmass: landmasses.first().map_or(0, |size| size * step * step),
+            islands: landmasses.len().saturating_sub(1),
+            height_histogram: HeightHistogram {
+                min,
+                bin_size,
+                counts,
+            },
+            layers,
+        }
+    }
+}
+
+/// Number of samples of each group of land samples connected by their sides
+fn landmass_sizes(land: &[bool], columns: usize) -> Vec<usize> {
+    let mut visited = vec![false; land.len()];
+    let mut sizes = vec![];
+    let mut queue = VecDeque::new();
+    for start in 0..land.len() {
+        if !land[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        queue.push_back(start);
+        let mut size = 0;
+        while let Some(i) = queue.pop_front() {
+            size += 1;
+            let (column, row) = (i % columns, i / columns);
+            let neighbours = [
+                (column > 0).then(|| i - 1),
+                (column + 1 < columns).then(|| i + 1),
+                (row > 0).then(|| i - columns),
+                (i + columns < land.len()).then(|| i + columns),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if land[next] && !visited[next] {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    sizes
+}
+
+impl fmt::Display for WorldReport {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "seed {}, region {}, {} samples every {} cells",
+            self.seed, self.region, self.samples, self.step
+        )?;
+        writeln!(
+            f,
+            "land {:.1} %, water {:.1} %",
+            self.land_fraction * 100.,
+            self.water_fraction * 100.
+        )?;
+        writeln!(
+            f,
+            "largest land mass {} cells, {} islands",
+            self.largest_landmass, self.islands
+        )?;
+        writeln!(f, "\nbiomes")?;
+        for area in &self.biomes {
+            writeln!(f, "  {:<24}{:>6.1} %", area.biome, area.fraction * 100.)?;
+        }
+        writeln!(f, "\n{:<18}{:>8}{:>8}{:>8}", "layers", "min", "max", "mean")?;
+        for layer in &self.layers {
+            writeln!(
+                f,
+                "  {:<16}{:>8.3}{:>8.3}{:>8.3}",
+                layer.name, layer.min, layer.max, layer.mean
+            )?;
+        }
+        writeln!(f, "\nheight")?;
+        let histogram = &self.height_histogram;
+        let most = histogram.counts.iter().copied().max().unwrap_or(0).max(1);
+        for (i, count) in histogram.counts.iter().enumerate() {
+            let from = histogram.min + histogram.bin_size * i as f64;
+            writeln!(
+                f,
+                "  {from:>5.1} .. {:>5.1} {:<40} {count}",
+                from + histogram.bin_size,
+                "#".repeat(count * 40 / most)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{landmass_sizes, WorldReport, HEIGHT_BINS};
+    use crate::{region::Region, WorldGen};
+
+    #[test]
+    fn test_landmass_sizes() {
+        #[rustfmt::skip]
+        let land = [
+            true, true, false, true,
+            false, true, false, true,
+            true, false, false, false,
+        ];
+        let mut sizes = landmass_sizes(&land, 4);
+        sizes.sort_unstable();
+        assert_eq!(sizes, [1, 2, 3]);
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn test_report() {
+        let world_gen = WorldGen::new(1., Some(2));
+        let region = Region::new(-100, -60, 200, 120);
+        let report = WorldReport::generate(&world_gen, region, 1);
+        assert_eq!(report.samples, region.len());
+        let total = report.biomes.iter().map(|b| b.fraction).sum::<f64>();
+        assert!((total - 1.).abs() < 1e-9);
+        assert!(report
+            .biomes
+            .windows(2)
+            .all(|w| w[0].fraction >= w[1].fraction));
+        assert_eq!(
+            report.height_histogram.counts.iter().sum::<usize>(),
+            region.len()
+        );
+        assert_eq!(report.height_histogram.counts.len(), HEIGHT_BINS);
+        assert!(report.largest_landmass > 0);
+        #[allow(clippy::cast_precision_loss)]
+        let land = (report.land_fraction * region.len() as f64).round() as usize;
+        assert!(report.largest_landmass <= land);
+        assert_eq!(report.layers.len(), 5);
+        assert!(report
+            .layers
+            .iter()
+            .all(|l| l.min <= l.mean && l.mean <= l.max));
+
+        let sampled = WorldReport::generate(&world_gen, region, 3);
+        assert_eq!(sampled.samples, 67 * 40);
+        assert!((sampled.land_fraction - report.land_fraction).abs() < 0.05);
+
+        let text = report.to_string();
+        assert!(text.contains("seed 2, region -100,-60,200,120"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["region"]["width"], 200);
+    }
+}
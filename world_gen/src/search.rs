@@ -0,0 +1,265 @@
+//! Search of the seeds whose world satisfies some constraints, to pick good starting worlds
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::{Range, RangeInclusive};
+
+use crate::{biome::BiomeType, WorldGen};
+
+/// What the world around the spawn must look like, every constraint is optional
+///
+/// The surroundings of the spawn are sampled every `step` cells, so features smaller than
+/// that can be missed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeedConstraints {
+    /// Cell where the players start
+    pub spawn: (isize, isize),
+    /// Biomes allowed at the spawn, any if empty
+    pub spawn_biomes: Vec<BiomeType>,
+    /// Max distance from the spawn to the ocean, in cells
+    pub ocean_within: Option<usize>,
+    /// Min number of different biomes within `radius` of the spawn
+    pub min_biomes: usize,
+    /// Range of the fraction of land within `radius` of the spawn
+    pub land_fraction: Option<RangeInclusive<f64>>,
+    /// Radius of the disk around the spawn where the biomes and the land are counted
+    pub radius: usize,
+    /// Distance between two samples
+    pub step: usize,
+}
+
+impl Default for SeedConstraints {
+    fn default() -> Self {
+        Self {
+            spawn: (0, 0),
+            spawn_biomes: vec![],
+            ocean_within: None,
+            min_biomes: 0,
+            land_fraction: None,
+            radius: 500,
+            step: 8,
+        }
+    }
+}
+
+/// Measures of a world satisfying the constraints
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SeedMatch {
+    pub seed: u64,
+    /// How comfortably the constraints are met, from 0 to 1
+    pub score: f64,
+    pub spawn_biome: BiomeType,
+    /// Distance from the spawn to the closest ocean sample, if the ocean is constrained
+    pub ocean_distance: Option<f64>,
+    /// Number of different biomes within the radius
+    pub biomes: usize,
+    /// Fraction of land within the radius
+    pub land_fraction: f64,
+}
+
+impl SeedConstraints {
+    #[must_use]
+    pub fn set_spawn(self, spawn: (isize, isize)) -> Self {
+        Self { spawn, ..self }
+    }
+    #[must_use]
+    pub fn set_spawn_biomes(self, spawn_biomes: Vec<BiomeType>) -> Self {
+        Self {
+            spawn_biomes,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_ocean_within(self, ocean_within: Option<usize>) -> Self {
+        Self {
+            ocean_within,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_min_biomes(self, min_biomes: usize) -> Self {
+        Self { min_biomes, ..self }
+    }
+    #[must_use]
+    pub fn set_land_fraction(self, land_fraction: Option<RangeInclusive<f64>>) -> Self {
+        Self {
+            land_fraction,
+            ..self
+        }
+    }
+    #[must_use]
+    pub fn set_radius(self, radius: usize) -> Self {
+        Self { radius, ..self }
+    }
+    #[must_use]
+    pub fn set_step(self, step: usize) -> Self {
+        Self { step, ..self }
+    }
+
+    /// Measures of the world around the spawn, `None` if a constraint isn't satisfied
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn evaluate(&self, world_gen: &WorldGen) -> Option<SeedMatch> {
+        let spawn_biome = world_gen.generate_cell(self.spawn).biome;
+        if !self.spawn_biomes.is_empty() && !self.spawn_biomes.contains(&spawn_biome) {
+            return None;
+        }
+
+        let (radius, ocean_within) = (self.radius as f64, self.ocean_within.map(|d| d as f64));
+        let reach = self.radius.max(self.ocean_within.unwrap_or(0)) as isize;
+        let mut biomes = [false; BiomeType::ALL.len()];
+        let (mut samples, mut land) = (0, 0);
+        let mut ocean_distance: Option<f64> = None;
+        for dy in (-reach..=reach).step_by(self.step.max(1)) {
+            for dx in (-reach..=reach).step_by(self.step.max(1)) {
+                let distance = (dx as f64).hypot(dy as f64);
+                if distance > reach as f64 {
+                    continue;
+                }
+                let biome = world_gen
+                    .generate_cell((self.spawn.0 + dx, self.spawn.1 + dy))
+                    .biome;
+                if distance <= radius {
+                    samples += 1;
+                    biomes[biome as usize] = true;
+                    if !biome.is_water() {
+                        land += 1;
+                    }
+                }
+                if ocean_within.is_some_and(|within| distance <= within) && biome.is_ocean() {
+                    ocean_distance =
+                        Some(ocean_distance.map_or(distance, |closest| closest.min(distance)));
+                }
+            }
+        }
+        let biomes = biomes.iter().filter(|present| **present).count();
+        let land_fraction = f64::from(land) / f64::from(samples);
+
+        // Margin of each constraint, 0 when barely met and 1 when met by far
+        let mut margins = vec![];
+        if let Some(within) = ocean_within {
+            margins.push(1. - ocean_distance? / within.max(1.));
+        }
+        if self.min_biomes > 0 {
+            if biomes < self.min_biomes {
+                return None;
+            }
+            margins.push(((biomes - self.min_biomes) as f64 / self.min_biomes as f64).min(1.));
+        }
+        if let Some(range) = &self.land_fraction {
+            if !range.contains(&land_fraction) {
+                return None;
+            }
+            let half = (range.end() - range.start()) / 2.;
+            let middle = range.start() + half;
+            margins.push(if half > 0. {
+                1. - (land_fraction - middle).abs() / half
+            } else {
+                1.
+            });
+        }
+        let score = if margins.is_empty() {
+            1.
+        } else {
+            margins.iter().sum::<f64>() / margins.len() as f64
+        };
+        Some(SeedMatch {
+            seed: world_gen.seed,
+            score,
+            spawn_biome,
+            ocean_distance,
+            biomes,
+            land_fraction,
+        })
+    }
+}
+
+/// Seeds whose world satisfies the constraints, evaluated in parallel, the best first
+#[must_use]
+pub fn search_seeds(
+    seeds: Range<u64>,
+    scale: f64,
+    constraints: &SeedConstraints,
+) -> Vec<SeedMatch> {
+    let mut matches = seeds
+        .into_par_iter()
+        .filter_map(|seed| constraints.evaluate(&WorldGen::new(scale, Some(seed))))
+        .collect::<Vec<_>>();
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.seed.cmp(&b.seed)));
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{search_seeds, SeedConstraints};
+    use crate::{BiomeType, WorldGen};
+
+    fn constraints() -> SeedConstraints {
+        SeedConstraints::default().set_radius(60).set_step(6)
+    }
+
+    #[test]
+    fn test_no_constraint() {
+        let matches = search_seeds(0..8, 1., &constraints());
+        assert_eq!(matches.len(), 8);
+        assert!(matches.iter().all(|m| m.score == 1.));
+        // Ties are sorted by seed
+        assert!(matches.windows(2).all(|w| w[0].seed < w[1].seed));
+        assert!(matches
+            .iter()
+            .all(|m| (0. ..=1.).contains(&m.land_fraction)));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let constraints = constraints()
+            .set_spawn((40, -20))
+            .set_ocean_within(Some(200))
+            .set_min_biomes(2)
+            .set_land_fraction(Some(0.2..=0.9));
+        let matches = search_seeds(0..16, 1., &constraints);
+        assert!(!matches.is_empty());
+        assert!(matches.windows(2).all(|w| w[0].score >= w[1].score));
+        for m in &matches {
+            assert!(m.ocean_distance.is_some_and(|d| d <= 200.));
+            assert!(m.biomes >= 2);
+            assert!((0.2..=0.9).contains(&m.land_fraction));
+            assert_eq!(
+                constraints.evaluate(&WorldGen::new(1., Some(m.seed))),
+                Some(m.clone())
+            );
+        }
+
+        let impossible = constraints.set_min_biomes(BiomeType::ALL.len() + 1);
+        assert!(search_seeds(0..4, 1., &impossible).is_empty());
+    }
+
+    #[test]
+    fn test_spawn_biomes() {
+        let spawn = (120, 35);
+        let constraints = constraints()
+            .set_spawn(spawn)
+            .set_spawn_biomes(vec![BiomeType::Plain, BiomeType::Forest]);
+        let matches = search_seeds(0..32, 1., &constraints);
+        for seed in 0..32 {
+            let biome = WorldGen::new(1., Some(seed)).generate_cell(spawn).biome;
+            let expected = matches!(biome, BiomeType::Plain | BiomeType::Forest);
+            assert_eq!(matches.iter().any(|m| m.seed == seed), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_biome() {
+        assert_eq!("plain".parse(), Ok(BiomeType::Plain));
+        assert_eq!(
+            "deep-temperate_water".parse(),
+            Ok(BiomeType::DeepTemperateWater)
+        );
+        assert!("swamp".parse::<BiomeType>().is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Search of a good cell for the players to start on
+
+use rayon::prelude::*;
+
+use crate::{
+    analysis::{Analysis, AnalysisSettings},
+    region::Region,
+    WorldGen,
+};
+
+/// Half the side of the first square searched around the cell, doubled until a spawn is found
+const FIRST_RADIUS: usize = 32;
+
+/// What the spawn and its surroundings must look like
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpawnCriteria {
+    /// Max slope of the spawn and of the city site in degrees
+    pub max_slope: f64,
+    /// Cells of elevation for a height of 1, as in [`AnalysisSettings`]
+    pub height_scale: f64,
+    /// Max distance from the spawn to a lake or the sea, in cells
+    pub water_within: Option<usize>,
+    /// Radius of the walkable and gentle area around the spawn, to build a city on
+    pub city_radius: Option<usize>,
+    /// Max distance along x or y between the spawn and the cell it's searched around
+    pub max_distance: usize,
+}
+
+impl Default for SpawnCriteria {
+    fn default() -> Self {
+        Self {
+            max_slope: 20.,
+            height_scale: AnalysisSettings::default().height_scale,
+            water_within: None,
+            city_radius: None,
+            max_distance: 1000,
+        }
+    }
+}
+
+impl SpawnCriteria {
+    #[must_use]
+    pub const fn set_max_slope(self, max_slope: f64) -> Self {
+        Self { max_slope, ..self }
+    }
+    #[must_use]
+    pub const fn set_height_scale(self, height_scale: f64) -> Self {
+        Self {
+            height_scale,
+            ..self
+        }
+    }
+    #[must_use]
+    pub const fn set_water_within(self, water_within: Option<usize>) -> Self {
+        Self {
+            water_within,
+            ..self
+        }
+    }
+    #[must_use]
+    pub const fn set_city_radius(self, city_radius: Option<usize>) -> Self {
+        Self {
+            city_radius,
+            ..self
+        }
+    }
+    #[must_use]
+    pub const fn set_max_distance(self, max_distance: usize) -> Self {
+        Self {
+            max_distance,
+            ..self
+        }
+    }
+}
+
+impl WorldGen {
+    /// Cell satisfying the criteria closest to `near`, `None` if there is none within
+    /// `max_distance`
+    ///
+    /// Squares twice as large as the previous one are searched around `near` until one holds a
+    /// spawn closer than its border, the cells outside of it being necessarily farther.
+    #[must_use]
+    pub fn find_spawn(
+        &self,
+        near: (isize, isize),
+        criteria: &SpawnCriteria,
+    ) -> Option<(isize, isize)> {
+        let mut radius = FIRST_RADIUS.min(criteria.max_distance);
+        loop {
+            let last = radius == criteria.max_distance;
+            match self.closest_spawn(near, radius, criteria) {
+                #[allow(clippy::cast_precision_loss)]
+                Some((spawn, distance)) if last || distance <= radius as f64 => return Some(spawn),
+                _ if last => return None,
+                _ => radius = (radius * 2).min(criteria.max_distance),
+            }
+        }
+    }
+
+    /// Spawn closest to `near` at most `radius` cells away along x and y, with its distance
+    #[allow(
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn closest_spawn(
+        &self,
+        near: (isize, isize),
+        radius: usize,
+        criteria: &SpawnCriteria,
+    ) -> Option<((isize, isize), f64)> {
+        // Enough cells around the square for the surroundings of the cells on its border
+        let margin = criteria
+            .water_within
+            .unwrap_or(0)
+            .max(criteria.city_radius.unwrap_or(0))
+            + 1;
+        let side = 2 * (radius + margin) + 1;
+        let region = Region::new(
+            near.0 - (radius + margin) as isize,
+            near.1 - (radius + margin) as isize,
+            side,
+            side,
+        );
+        let analysis = Analysis::generate(
+            self,
+            region,
+            AnalysisSettings::default().set_height_scale(criteria.height_scale),
+        );
+        let walkable = analysis
+            .biomes()
+            .into_par_iter()
+            .zip(analysis.slope())
+            .map(|(biome, slope)| biome.is_walkable() && f64::from(slope) <= criteria.max_slope)
+            .collect::<Vec<_>>();
+        let water_distance = criteria.water_within.map(|_| analysis.water_distance());
+        let blocked = criteria.city_radius.map(|_| {
+            let blocked = walkable.iter().map(|w| !w).collect::<Vec<_>>();
+            analysis.distances(&blocked)
+        });
+
+        (0..region.len())
+            .into_par_iter()
+            .filter(|&i| {
+                let (x, y) = region.cell(i);
+                x.abs_diff(near.0) <= radius
+                    && y.abs_diff(near.1) <= radius
+                    && walkable[i]
+                    && criteria
+                        .water_within
+                        .zip(water_distance.as_ref())
+                        .is_none_or(|(within, d)| f64::from(d[i]) <= within as f64)
+                    && criteria
+                        .city_radius
+                        .zip(blocked.as_ref())
+                        .is_none_or(|(radius, d)| f64::from(d[i]) > radius as f64)
+            })
+            .map(|i| {
+                let (x, y) = region.cell(i);
+                let distance = ((x - near.0) as f64).hypot((y - near.1) as f64);
+                ((x, y), distance)
+            })
+            // The first cell on ties, north first, so that the spawn doesn't depend on the threads
+            .min_by(|a, b| {
+                a.1.total_cmp(&b.1)
+                    .then(b.0 .1.cmp(&a.0 .1))
+                    .then(a.0 .0.cmp(&b.0 .0))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpawnCriteria;
+    use crate::{analysis::Analysis, region::Region, WorldGen};
+
+    /// If the cell is a spawn, checked cell by cell
+    #[allow(clippy::cast_possible_wrap, clippy::cast_precision_loss)]
+    fn is_spawn(world_gen: &WorldGen, (x, y): (isize, isize), criteria: &SpawnCriteria) -> bool {
+        let reach = criteria
+            .water_within
+            .unwrap_or(0)
+            .max(criteria.city_radius.unwrap_or(0)) as isize;
+        let region = Region::new(
+            x - reach,
+            y - reach,
+            2 * reach as usize + 1,
+            2 * reach as usize + 1,
+        );
+        let analysis = Analysis::generate(world_gen, region, Default::default());
+        let (biomes, slopes) = (analysis.biomes(), analysis.slope());
+        let within = |radius: usize| {
+            (0..region.len()).filter(move |i| {
+                let (cx, cy) = region.cell(*i);
+                ((cx - x) as f64).hypot((cy - y) as f64) <= radius as f64
+            })
+        };
+        let walkable =
+            |i: usize| biomes[i].is_walkable() && f64::from(slopes[i]) <= criteria.max_slope;
+        walkable(region.len() / 2)
+            && criteria
+                .water_within
+                .is_none_or(|d| within(d).any(|i| biomes[i].is_water()))
+            && criteria.city_radius.is_none_or(|d| within(d).all(walkable))
+    }
+
+    #[test]
+    fn test_find_spawn() {
+        let world_gen = WorldGen::new(1., Some(1));
+        let criteria = SpawnCriteria::default();
+        for near in [(0, 0), (700, -300), (-2000, 1500)] {
+            let spawn = world_gen.find_spawn(near, &criteria).unwrap();
+            assert!(is_spawn(&world_gen, spawn, &criteria));
+            // Already a good spawn, or none closer
+            if is_spawn(&world_gen, near, &criteria) {
+                assert_eq!(spawn, near);
+            }
+        }
+
+        let criteria = criteria
+            .set_water_within(Some(30))
+            .set_city_radius(Some(10));
+        let spawn = world_gen.find_spawn((-2000, 1500), &criteria).unwrap();
+        assert!(is_spawn(&world_gen, spawn, &criteria));
+        // Not farther than the spawns found in smaller squares
+        #[allow(clippy::cast_precision_loss)]
+        let distance = |(x, y): (isize, isize)| ((x + 2000) as f64).hypot((y - 1500) as f64);
+        let side = spawn.0.abs_diff(-2000).max(spawn.1.abs_diff(1500));
+        for max_distance in [side / 2, side.saturating_sub(1)] {
+            let smaller = criteria.clone().set_max_distance(max_distance);
+            if let Some(other) = world_gen.find_spawn((-2000, 1500), &smaller) {
+                assert!(distance(other) >= distance(spawn));
+            }
+        }
+
+        let impossible = criteria.set_max_slope(-1.).set_max_distance(20);
+        assert_eq!(world_gen.find_spawn((0, 0), &impossible), None);
+    }
+}
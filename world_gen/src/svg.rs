@@ -0,0 +1,322 @@
+//! Vector rendering of the generated cities
+
+use std::fmt::{self, Write};
+
+use crate::{
+    city_generation::{BuildingType, CityGenerator},
+    geojson::simplify,
+};
+
+/// Size of the image for one unit of the city
+const PIXELS_PER_UNIT: i32 = 2;
+/// Roads shorter than this are paths leading to a single building
+const PATH_LENGTH: usize = 10;
+const BACKGROUND_COLOR: &str = "#f4f1e8";
+const WALL_COLOR: &str = "#6b6b6b";
+
+/// Kind of road, drawn wider the more important it is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RoadClass {
+    Main,
+    Street,
+    Path,
+}
+
+impl RoadClass {
+    const ALL: [Self; 3] = [Self::Main, Self::Street, Self::Path];
+
+    const fn width(self) -> f64 {
+        match self {
+            Self::Main => 4.,
+            Self::Street => 2.,
+            Self::Path => 1.,
+        }
+    }
+    const fn color(self) -> &'static str {
+        match self {
+            Self::Main => "#8b4513",
+            Self::Street => "#b07a4f",
+            Self::Path => "#c9a882",
+        }
+    }
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Main => "Main road",
+            Self::Street => "Street",
+            Self::Path => "Path",
+        }
+    }
+}
+
+const fn building_color(building_type: BuildingType) -> &'static str {
+    match building_type {
+        BuildingType::House => "#d9a066",
+        BuildingType::Farm => "#b6d7a8",
+        BuildingType::Shop => "#6fa8dc",
+        BuildingType::Workshop => "#b4a7d6",
+        BuildingType::Tavern => "#e06666",
+        BuildingType::Warehouse => "#999999",
+        BuildingType::TownHall => "#f1c232",
+        BuildingType::Market => "#f6b26b",
+        BuildingType::Temple => "#fdfdfd",
+        BuildingType::Barracks => "#a61c00",
+        BuildingType::Guildhall => "#8e7cc3",
+    }
+}
+
+const fn building_name(building_type: BuildingType) -> &'static str {
+    match building_type {
+        BuildingType::House => "House",
+        BuildingType::Farm => "Farm",
+        BuildingType::Shop => "Shop",
+        BuildingType::Workshop => "Workshop",
+        BuildingType::Tavern => "Tavern",
+        BuildingType::Warehouse => "Warehouse",
+        BuildingType::TownHall => "Town hall",
+        BuildingType::Market => "Market",
+        BuildingType::Temple => "Temple",
+        BuildingType::Barracks => "Barracks",
+        BuildingType::Guildhall => "Guildhall",
+    }
+}
+
+/// Render a city as an SVG document
+///
+/// Buildings are filled by type, roads are wider the more important they are and important
+/// buildings are labelled. A legend sits in the top left corner.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn city_svg(city_gen: &CityGenerator) -> String {
+    let (min_x, min_y) = (city_gen.min_x, city_gen.min_y);
+    let (width, height) = (city_gen.max_x - min_x, city_gen.max_y - min_y);
+    let mut svg = String::new();
+    push_line(
+        &mut svg,
+        format_args!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{min_x} {min_y} {width} {height}">"#,
+            width * PIXELS_PER_UNIT,
+            height * PIXELS_PER_UNIT,
+        ),
+    );
+    push_line(
+        &mut svg,
+        format_args!(
+            r#"<rect x="{min_x}" y="{min_y}" width="{width}" height="{height}" fill="{BACKGROUND_COLOR}"/>"#
+        ),
+    );
+
+    // Smaller roads first so that the main ones are drawn over them
+    for class in RoadClass::ALL.iter().rev() {
+        push_line(
+            &mut svg,
+            format_args!(
+                r#"<g fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round">"#,
+                class.color(),
+                class.width()
+            ),
+        );
+        for (i, road) in city_gen.roads.iter().enumerate() {
+            if road_class(city_gen, i) != *class || road.len() < 2 {
+                continue;
+            }
+            push_line(
+                &mut svg,
+                format_args!(r#"<polyline points="{}"/>"#, points(&simplify(road, false))),
+            );
+        }
+        svg.push_str("</g>\n");
+    }
+
+    if let Some(wall) = &city_gen.wall {
+        push_line(
+            &mut svg,
+            format_args!(
+                r#"<polygon points="{}" fill="none" stroke="{WALL_COLOR}" stroke-width="3"/>"#,
+                points(&wall.outline)
+            ),
+        );
+        for (x, y) in wall.towers.iter().map(|tower| cell_point(*tower)) {
+            push_line(
+                &mut svg,
+                format_args!(r#"<circle cx="{x}" cy="{y}" r="3" fill="{WALL_COLOR}"/>"#),
+            );
+        }
+        for (x, y) in wall.gates.iter().map(|gate| cell_point(*gate)) {
+            push_line(
+                &mut svg,
+                format_args!(
+                    r#"<rect x="{}" y="{}" width="3" height="3" fill="{}"/>"#,
+                    x - 1.5,
+                    y - 1.5,
+                    RoadClass::Main.color()
+                ),
+            );
+        }
+    }
+
+    svg.push_str("<g stroke=\"#333333\" stroke-width=\"0.5\">\n");
+    for building in city_gen.buildings.values() {
+        push_line(
+            &mut svg,
+            format_args!(
+                r#"<polygon points="{}" fill="{}"/>"#,
+                points(&building.footprint.vertices),
+                building_color(building.building_type)
+            ),
+        );
+    }
+    svg.push_str("</g>\n");
+
+    // Labels on top of everything, with a halo to stay readable over roads
+    svg.push_str(
+        "<g font-family=\"sans-serif\" text-anchor=\"middle\" dominant-baseline=\"middle\" \
+         stroke=\"#ffffff\" stroke-width=\"2\" paint-order=\"stroke\">\n",
+    );
+    for building in city_gen.buildings.values().filter(|b| b.is_important) {
+        let name = building_name(building.building_type);
+        let font_size = (f64::from(building.width) / name.len() as f64 * 1.6).clamp(6., 16.);
+        let (x, y) = cell_point((building.x, building.y));
+        push_line(
+            &mut svg,
+            format_args!(
+                r#"<text x="{}" y="{}" font-size="{font_size:.1}">{name}</text>"#,
+                x + f64::from(building.width) / 2.,
+                y + f64::from(building.height) / 2.,
+            ),
+        );
+    }
+    svg.push_str("</g>\n");
+
+    legend(&mut svg, city_gen);
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn road_class(city_gen: &CityGenerator, index: usize) -> RoadClass {
+    if index < city_gen.main_roads {
+        RoadClass::Main
+    } else if city_gen.roads[index].len() < PATH_LENGTH {
+        RoadClass::Path
+    } else {
+        RoadClass::Street
+    }
+}
+
+/// Append a line to the document
+fn push_line(svg: &mut String, line: fmt::Arguments) {
+    svg.write_fmt(line)
+        .expect("writing to a string never fails");
+    svg.push('\n');
+}
+
+/// Center of a cell in the document, where roads, walls and buildings meet
+fn cell_point((x, y): (i32, i32)) -> (f64, f64) {
+    (f64::from(x) + 0.5, f64::from(y) + 0.5)
+}
+
+/// Cells as the points of a polyline or polygon
+fn points(cells: &[(i32, i32)]) -> String {
+    cells
+        .iter()
+        .map(|cell| {
+            let (x, y) = cell_point(*cell);
+            format!("{x},{y}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Building types present in the city and road classes, sized with the city
+#[allow(clippy::cast_precision_loss)]
+fn legend(svg: &mut String, city_gen: &CityGenerator) {
+    let types = BuildingType::ALL
+        .into_iter()
+        .filter(|t| city_gen.buildings.values().any(|b| b.building_type == *t))
+        .collect::<Vec<_>>();
+    let size = city_gen.max_x - city_gen.min_x;
+    let line = f64::from((size / 60).max(10));
+    let (x, y) = (
+        f64::from(city_gen.min_x) + line,
+        f64::from(city_gen.min_y) + line,
+    );
+    let rows = (types.len() + RoadClass::ALL.len()) as f64;
+
+    push_line(
+        svg,
+        format_args!(
+            r##"<g font-family="sans-serif" font-size="{:.1}"><rect x="{x:.1}" y="{y:.1}" width="{:.1}" height="{:.1}" fill="#ffffff" fill-opacity="0.85" stroke="#333333" stroke-width="0.5"/>"##,
+            line * 0.7,
+            line * 8.,
+            line * (rows + 1.),
+        ),
+    );
+    for (i, building_type) in types.into_iter().enumerate() {
+        let row = y + line * (i as f64 + 0.5);
+        push_line(
+            svg,
+            format_args!(
+                r##"<rect x="{:.1}" y="{row:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="#333333" stroke-width="0.5"/><text x="{:.1}" y="{:.1}">{}</text>"##,
+                x + line * 0.5,
+                line * 0.8,
+                line * 0.8,
+                building_color(building_type),
+                x + line * 1.8,
+                row + line * 0.65,
+                building_name(building_type),
+            ),
+        );
+    }
+    for (i, class) in RoadClass::ALL.into_iter().enumerate() {
+        let row = y + line * ((rows - 3.) + i as f64 + 0.9);
+        push_line(
+            svg,
+            format_args!(
+                r#"<line x1="{:.1}" y1="{row:.1}" x2="{:.1}" y2="{row:.1}" stroke="{}" stroke-width="{}"/><text x="{:.1}" y="{:.1}">{}</text>"#,
+                x + line * 0.5,
+                x + line * 1.3,
+                class.color(),
+                class.width(),
+                x + line * 1.8,
+                row + line * 0.25,
+                class.name(),
+            ),
+        );
+    }
+    svg.push_str("</g>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::city_svg;
+    use crate::city_generation::{CityConfig, CityGenerator};
+
+    #[test]
+    fn test_city_svg() {
+        let mut city_gen = CityGenerator::new(CityConfig::default().set_seed(1));
+        city_gen.generate().unwrap();
+        let svg = city_svg(&city_gen);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        // One polygon per building, the legend only has rectangles
+        assert_eq!(svg.matches("<polygon").count(), city_gen.buildings.len());
+        assert_eq!(
+            svg.matches("Town hall").count(),
+            2,
+            "one label and one legend entry"
+        );
+        assert!(svg.contains("Main road"));
+        // Buildings and roads go through the same cell to document transform
+        let building = city_gen.buildings.values().next().unwrap();
+        let (x, y) = building.footprint.vertices[0];
+        assert!(svg.contains(&format!(
+            r#"points="{},{} "#,
+            f64::from(x) + 0.5,
+            f64::from(y) + 0.5
+        )));
+        let (x, y) = city_gen.roads[0][0];
+        assert!(svg.contains(&format!(
+            r#"points="{},{} "#,
+            f64::from(x) + 0.5,
+            f64::from(y) + 0.5
+        )));
+    }
+}
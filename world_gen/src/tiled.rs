@@ -0,0 +1,275 @@
+//! Export of the generated content as maps for the Tiled editor, in its JSON format
+//!
+//! The biomes are a tile layer using a generated tileset, one tile per biome. The city is an
+//! object layer centered on the map where one unit of the city is one pixel.
+
+use image::{ImageBuffer, Rgb};
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::{biome::BiomeType, city_generation::CityGenerator, geojson::simplify, WorldGen};
+
+/// Size of a tile in pixels
+pub const TILE_SIZE: u32 = 16;
+/// Version of the JSON map format
+const TILED_VERSION: &str = "1.10";
+
+/// Tileset image with one tile per biome, in a single row
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn biome_tileset_image() -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(
+        BiomeType::ALL.len() as u32 * TILE_SIZE,
+        TILE_SIZE,
+        |x, _| Rgb(BiomeType::ALL[(x / TILE_SIZE) as usize].color()),
+    )
+}
+
+/// Tiled map of the biomes of `size` cells starting at `origin`, with the city on top of them
+///
+/// North is up: the first row of the map is the one with the highest y. `tileset_image` is
+/// the path of the image saved from [`biome_tileset_image`], relative to the map. The seed and
+/// the `scale` `world_gen` was created with are saved as properties to generate it again.
+#[must_use]
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss
+)]
+pub fn tiled_map(
+    world_gen: &WorldGen,
+    scale: f64,
+    origin: (isize, isize),
+    size: (usize, usize),
+    city_gen: Option<&CityGenerator>,
+    tileset_image: &str,
+) -> Value {
+    let (width, height) = size;
+    let data = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|row| {
+            let y = origin.1 + (height - 1 - row) as isize;
+            (0..width).map(move |x| {
+                let biome = world_gen.generate_cell((origin.0 + x as isize, y)).biome;
+                // Global tile IDs start at 1, 0 is an empty tile
+                biome as u32 + 1
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut layers = vec![json!({
+        "id": 1,
+        "name": "biomes",
+        "type": "tilelayer",
+        "x": 0,
+        "y": 0,
+        "width": width,
+        "height": height,
+        "opacity": 1,
+        "visible": true,
+        "data": data,
+    })];
+    let mut properties = vec![
+        property("seed", "string", world_gen.seed.to_string()),
+        property("scale", "float", scale),
+    ];
+    let mut next_object_id = 1;
+    if let Some(city_gen) = city_gen {
+        let center = (
+            (width as i32 * TILE_SIZE as i32) / 2,
+            (height as i32 * TILE_SIZE as i32) / 2,
+        );
+        let (objects, next_id) = city_objects(city_gen, center);
+        next_object_id = next_id;
+        layers.push(json!({
+            "id": 2,
+            "name": "city",
+            "type": "objectgroup",
+            "x": 0,
+            "y": 0,
+            "opacity": 1,
+            "visible": true,
+            "draworder": "topdown",
+            "objects": objects,
+        }));
+        properties.push(property(
+            "city_config",
+            "string",
+            serde_json::to_string(city_gen.config()).unwrap_or_default(),
+        ));
+    }
+
+    json!({
+        "type": "map",
+        "version": TILED_VERSION,
+        "orientation": "orthogonal",
+        "renderorder": "right-down",
+        "infinite": false,
+        "width": width,
+        "height": height,
+        "tilewidth": TILE_SIZE,
+        "tileheight": TILE_SIZE,
+        "nextlayerid": layers.len() + 1,
+        "nextobjectid": next_object_id,
+        "layers": layers,
+        "tilesets": [biome_tileset(tileset_image)],
+        "properties": properties,
+    })
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn biome_tileset(image: &str) -> Value {
+    let count = BiomeType::ALL.len() as u32;
+    let tiles = BiomeType::ALL
+        .iter()
+        .enumerate()
+        .map(|(id, biome)| {
+            json!({
+                "id": id,
+                "type": "biome",
+                "properties": [property("biome", "string", format!("{biome:?}"))],
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "firstgid": 1,
+        "name": "biomes",
+        "image": image,
+        "imagewidth": count * TILE_SIZE,
+        "imageheight": TILE_SIZE,
+        "tilewidth": TILE_SIZE,
+        "tileheight": TILE_SIZE,
+        "tilecount": count,
+        "columns": count,
+        "margin": 0,
+        "spacing": 0,
+        "tiles": tiles,
+    })
+}
+
+/// Buildings as polygons and roads as polylines, the center of the city at `center`
+///
+/// Returns the objects and the next free object ID.
+fn city_objects(city_gen: &CityGenerator, center: (i32, i32)) -> (Vec<Value>, usize) {
+    let offset = (
+        center.0 - (city_gen.min_x + city_gen.max_x) / 2,
+        center.1 - (city_gen.min_y + city_gen.max_y) / 2,
+    );
+    let mut objects = vec![];
+    // Points of Tiled objects are relative to their position, the first one
+    let mut object = |class: &str,
+                      name: String,
+                      shape: &str,
+                      points: &[(i32, i32)],
+                      extra: Option<(bool, usize)>| {
+        let (x, y) = points[0];
+        let points = points
+            .iter()
+            .map(|(px, py)| json!({ "x": px - x, "y": py - y }))
+            .collect::<Vec<_>>();
+        let mut properties = vec![];
+        if let Some((important, building_id)) = extra {
+            properties.push(property("important", "bool", important));
+            properties.push(property("building_id", "int", building_id));
+        }
+        let mut object = json!({
+            "id": objects.len() + 1,
+            "name": name,
+            "type": class,
+            "x": x + offset.0,
+            "y": y + offset.1,
+            "width": 0,
+            "height": 0,
+            "rotation": 0,
+            "visible": true,
+            "properties": properties,
+        });
+        object[shape] = points.into();
+        objects.push(object);
+    };
+    for building in city_gen.buildings.values() {
+        object(
+            "building",
+            format!("{:?}", building.building_type),
+            "polygon",
+            &building.footprint.vertices,
+            Some((building.is_important, building.id)),
+        );
+    }
+    for (i, road) in city_gen.roads.iter().enumerate() {
+        if road.len() < 2 {
+            continue;
+        }
+        let name = if i < city_gen.main_roads {
+            "main road"
+        } else {
+            "road"
+        };
+        object(
+            "road",
+            name.to_string(),
+            "polyline",
+            &simplify(road, false),
+            None,
+        );
+    }
+    let next_id = objects.len() + 1;
+    (objects, next_id)
+}
+
+fn property(name: &str, kind: &str, value: impl Into<Value>) -> Value {
+    json!({ "name": name, "type": kind, "value": value.into() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{biome_tileset_image, tiled_map, TILE_SIZE};
+    use crate::{
+        biome::BiomeType,
+        city_generation::{CityConfig, CityGenerator},
+        WorldGen,
+    };
+
+    #[test]
+    fn test_tiled_map() {
+        let world_gen = WorldGen::new(1.5, Some(3));
+        let mut city_gen = CityGenerator::new(CityConfig::default().set_seed(3));
+        city_gen.generate().unwrap();
+        let map = tiled_map(
+            &world_gen,
+            1.5,
+            (-8, -4),
+            (16, 8),
+            Some(&city_gen),
+            "biomes.png",
+        );
+
+        let data = map["layers"][0]["data"].as_array().unwrap();
+        assert_eq!(data.len(), 16 * 8);
+        let tilecount = map["tilesets"][0]["tilecount"].as_u64().unwrap();
+        assert!(data
+            .iter()
+            .all(|gid| (1..=tilecount).contains(&gid.as_u64().unwrap())));
+        // The first row is the northmost one
+        let top_left = world_gen.generate_cell((-8, 3)).biome as u64 + 1;
+        assert_eq!(data[0].as_u64(), Some(top_left));
+
+        let objects = map["layers"][1]["objects"].as_array().unwrap();
+        let buildings = objects.iter().filter(|o| o["type"] == "building").count();
+        assert_eq!(buildings, city_gen.buildings.len());
+        assert_eq!(map["nextobjectid"], objects.len() + 1);
+        assert_eq!(map["properties"][0]["value"], "3");
+        assert_eq!(map["properties"][1]["value"], 1.5);
+
+        let image = biome_tileset_image();
+        assert_eq!(image.width(), tilecount as u32 * TILE_SIZE);
+    }
+
+    #[test]
+    fn test_biome_tile_ids() {
+        // Tile IDs are the biomes cast to integers, they must match their index in the tileset
+        for (i, biome) in BiomeType::ALL.into_iter().enumerate() {
+            assert_eq!(biome as usize, i);
+        }
+    }
+}
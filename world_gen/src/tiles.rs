@@ -0,0 +1,241 @@
+//! Slippy map tiles of the biomes, to browse a world in a web browser
+//!
+//! Tiles are written as `{z}/{x}/{y}.png` next to a viewer page. At the highest zoom level one
+//! pixel is one cell, and each level below halves the resolution down to a single tile covering
+//! the whole region at zoom 0. A pixel of a level is the most common biome of the four pixels it
+//! covers in the level above, so that small biomes fade out instead of flickering in and out like
+//! they would if cells were sampled at the center of the pixels.
+
+use image::{ImageBuffer, ImageResult, Rgba};
+use rayon::prelude::*;
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{biome::BiomeType, region::Region, render::generate_region, WorldGen};
+
+/// Size of a tile in pixels
+pub const TILE_SIZE: usize = 256;
+/// Pixel outside of the region, transparent
+const NO_BIOME: u8 = u8::MAX;
+const VIEWER: &str = include_str!("tiles_viewer.html");
+
+/// Zoom levels of the tiles of a region, anchored on its north west corner
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TilePyramid {
+    pub region: Region,
+    /// Level where one pixel is one cell
+    pub max_zoom: u32,
+}
+
+impl TilePyramid {
+    /// Pyramid with just enough levels for a single tile to cover the region at zoom 0
+    #[must_use]
+    pub fn new(region: Region) -> Self {
+        let mut max_zoom = 0;
+        while TILE_SIZE << max_zoom < region.width.max(region.height) {
+            max_zoom += 1;
+        }
+        Self { region, max_zoom }
+    }
+
+    /// Number of cells along the side of a pixel at this zoom level
+    #[must_use]
+    pub const fn cells_per_pixel(self, zoom: u32) -> usize {
+        1 << (self.max_zoom - zoom)
+    }
+
+    /// Number of columns and rows of tiles with some of the region at this zoom level
+    #[must_use]
+    pub const fn tile_count(self, zoom: u32) -> (usize, usize) {
+        let cells = TILE_SIZE * self.cells_per_pixel(zoom);
+        (
+            self.region.width.div_ceil(cells),
+            self.region.height.div_ceil(cells),
+        )
+    }
+
+    /// Write the tiles of every level in `dir`, returns the number of tiles written
+    ///
+    /// Only the tiles of the highest level are generated, in parallel, the other ones are
+    /// downsampled from them.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a tile can't be written.
+    pub fn render(&self, world_gen: &WorldGen, dir: &Path) -> ImageResult<usize> {
+        let written = AtomicUsize::new(0);
+        self.render_tile(world_gen, dir, (0, 0, 0), &written)?;
+        Ok(written.into_inner())
+    }
+
+    /// Biomes of the tile after writing it and its children, `None` if it's outside the region
+    fn render_tile(
+        &self,
+        world_gen: &WorldGen,
+        dir: &Path,
+        (zoom, x, y): (u32, usize, usize),
+        written: &AtomicUsize,
+    ) -> ImageResult<Option<Vec<u8>>> {
+        let (columns, rows) = self.tile_count(zoom);
+        if x >= columns || y >= rows {
+            return Ok(None);
+        }
+        let biomes = if zoom == self.max_zoom {
+            self.generate_tile(world_gen, x, y)
+        } else {
+            let children = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .into_par_iter()
+                .map(|(dx, dy)| {
+                    self.render_tile(world_gen, dir, (zoom + 1, 2 * x + dx, 2 * y + dy), written)
+                })
+                .collect::<ImageResult<Vec<_>>>()?;
+            downsample(&children)
+        };
+
+        let tile_dir = dir.join(zoom.to_string()).join(x.to_string());
+        fs::create_dir_all(&tile_dir)?;
+        tile_image(&biomes).save(tile_dir.join(format!("{y}.png")))?;
+        written.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(biomes))
+    }
+
+    /// Biomes of a tile of the highest level, row by row with the northmost row first
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn generate_tile(&self, world_gen: &WorldGen, x: usize, y: usize) -> Vec<u8> {
+        let region = self.region;
+        let (column, row) = (x * TILE_SIZE, y * TILE_SIZE);
+        let width = TILE_SIZE.min(region.width - column);
+        let height = TILE_SIZE.min(region.height - row);
+        let cells = generate_region(
+            world_gen,
+            Region::new(
+                region.x + column as isize,
+                region.y + (region.height - row - height) as isize,
+                width,
+                height,
+            ),
+        );
+        let mut biomes = vec![NO_BIOME; TILE_SIZE * TILE_SIZE];
+        for (i, cell) in cells.iter().enumerate() {
+            biomes[(i / width) * TILE_SIZE + i % width] = cell.biome as u8;
+        }
+        biomes
+    }
+
+    /// Page browsing the tiles written in the same directory
+    #[must_use]
+    pub fn viewer_html(&self, title: &str) -> String {
+        VIEWER
+            .replace("{{TITLE}}", title)
+            .replace("{{TILE_SIZE}}", &TILE_SIZE.to_string())
+            .replace("{{MAX_ZOOM}}", &self.max_zoom.to_string())
+            .replace("{{REGION_X}}", &self.region.x.to_string())
+            .replace("{{REGION_Y}}", &self.region.y.to_string())
+            .replace("{{REGION_WIDTH}}", &self.region.width.to_string())
+            .replace("{{REGION_HEIGHT}}", &self.region.height.to_string())
+    }
+}
+
+/// Tile made of the top left, top right, bottom left and bottom right `children` at half size
+fn downsample(children: &[Option<Vec<u8>>]) -> Vec<u8> {
+    let half = TILE_SIZE / 2;
+    let mut biomes = vec![NO_BIOME; TILE_SIZE * TILE_SIZE];
+    for (i, child) in children.iter().enumerate() {
+        let Some(child) = child else {
+            continue;
+        };
+        let (left, top) = ((i % 2) * half, (i / 2) * half);
+        for y in 0..half {
+            for x in 0..half {
+                let at = |dx, dy| child[(2 * y + dy) * TILE_SIZE + 2 * x + dx];
+                biomes[(top + y) * TILE_SIZE + left + x] =
+                    most_common([at(0, 0), at(1, 0), at(0, 1), at(1, 1)]);
+            }
+        }
+    }
+    biomes
+}
+
+/// Most common biome, the first one on ties, ignoring the pixels outside of the region
+fn most_common(biomes: [u8; 4]) -> u8 {
+    let mut best = (0, NO_BIOME);
+    for biome in biomes.into_iter().filter(|b| *b != NO_BIOME) {
+        let count = biomes.iter().filter(|b| **b == biome).count();
+        if count > best.0 {
+            best = (count, biome);
+        }
+    }
+    best.1
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn tile_image(biomes: &[u8]) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(TILE_SIZE as u32, TILE_SIZE as u32, |x, y| {
+        match biomes[y as usize * TILE_SIZE + x as usize] {
+            NO_BIOME => Rgba([0; 4]),
+            biome => {
+                let [r, g, b] = BiomeType::ALL[biome as usize].color();
+                Rgba([r, g, b, 255])
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{most_common, TilePyramid, NO_BIOME, TILE_SIZE};
+    use crate::{region::Region, WorldGen};
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(TilePyramid::new(Region::new(0, 0, 256, 10)).max_zoom, 0);
+        let pyramid = TilePyramid::new(Region::new(0, 0, 300, 1000));
+        assert_eq!(pyramid.max_zoom, 2);
+        assert_eq!(pyramid.cells_per_pixel(0), 4);
+        assert_eq!(pyramid.tile_count(2), (2, 4));
+        assert_eq!(pyramid.tile_count(1), (1, 2));
+        assert_eq!(pyramid.tile_count(0), (1, 1));
+    }
+
+    #[test]
+    fn test_most_common() {
+        assert_eq!(most_common([1, 2, 2, 3]), 2);
+        assert_eq!(most_common([1, 2, 3, 2]), 2);
+        assert_eq!(most_common([4, 5, 5, 4]), 4);
+        assert_eq!(most_common([NO_BIOME, NO_BIOME, NO_BIOME, 7]), 7);
+        assert_eq!(most_common([NO_BIOME; 4]), NO_BIOME);
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn test_render() {
+        let world_gen = WorldGen::new(1., Some(6));
+        let region = Region::new(-100, 40, 300, 200);
+        let pyramid = TilePyramid::new(region);
+        // Unique to the process so that concurrent runs don't share files
+        let dir = std::env::temp_dir().join(format!("world_gen_tiles_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // 2 tiles at zoom 1 and 1 at zoom 0
+        assert_eq!(pyramid.render(&world_gen, &dir).unwrap(), 3);
+
+        let tile = image::open(dir.join("1/1/0.png")).unwrap().into_rgba8();
+        assert_eq!(tile.dimensions(), (TILE_SIZE as u32, TILE_SIZE as u32));
+        // North west cell of the second tile, and the first pixel past the region
+        let cell = world_gen.generate_cell((156, 239));
+        let [r, g, b] = cell.biome.color();
+        assert_eq!(tile.get_pixel(0, 0).0, [r, g, b, 255]);
+        assert_eq!(tile.get_pixel(44, 0).0[3], 0);
+        assert_eq!(tile.get_pixel(0, 200).0[3], 0);
+        let top = image::open(dir.join("0/0/0.png")).unwrap().into_rgba8();
+        assert_eq!(top.get_pixel(149, 99).0[3], 255);
+        assert_eq!(top.get_pixel(150, 100).0[3], 0);
+
+        let html = pyramid.viewer_html("test");
+        assert!(html.contains("const MAX_ZOOM = 1;"));
+        assert!(!html.contains("{{"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
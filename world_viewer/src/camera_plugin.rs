@@ -1,4 +1,8 @@
 use bevy::{input::mouse::MouseMotion, prelude::*};
+use world_gen::spawn::SpawnCriteria;
+
+use crate::terrain::{HEIGHT_SCALE, RESOLUTION, WorldGenRes, cell_translation};
+
 pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
@@ -11,10 +15,18 @@ impl Plugin for CameraPlugin {
 #[require(Camera3d)]
 pub struct MainCamera;
 
-fn initialize_camera(mut commands: Commands) {
+/// Start a bit above the spawn closest to the origin, looking at the ground ahead
+///
+/// The slopes are measured with the heights of the scene, a cell being `1 / RESOLUTION` wide.
+fn initialize_camera(mut commands: Commands, world_gen: Res<WorldGenRes>) {
+    let criteria =
+        SpawnCriteria::default().set_height_scale(f64::from(HEIGHT_SCALE) * f64::from(RESOLUTION));
+    let spawn = world_gen.0.find_spawn((0, 0), &criteria).unwrap_or((0, 0));
+    let ground = cell_translation(&world_gen.0, spawn);
     commands.spawn((
         MainCamera,
-        Transform::from_xyz(0.0, 100.0, 0.0).looking_at(Vec3::ZERO, Vec3::Y),
+        Transform::from_translation(ground + Vec3::new(0.0, 5.0, 0.0))
+            .looking_at(ground + Vec3::new(0.0, 0.0, 20.0), Vec3::Y),
     ));
 }
 
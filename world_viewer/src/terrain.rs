@@ -3,13 +3,18 @@ use bevy::mesh::Indices;
 use bevy::prelude::*;
 use bevy::render::render_resource::PrimitiveTopology;
 use bevy_water::material::{StandardWaterMaterial, WaterMaterial};
-use world_gen::WorldGen;
+use world_gen::{
+    WorldGen,
+    mesh::{MeshSettings, TerrainMesh},
+};
 
 use crate::camera_plugin::MainCamera;
 
 const SIZE: [u32; 2] = [8; 2];
-const RESOLUTION: u32 = 32;
+pub(crate) const RESOLUTION: u32 = 32;
 const RENDER_DISTANCE: f32 = 16.0; // Radius in chunks
+/// Height in the scene of a difference of 1 in the heights of the cells
+pub(crate) const HEIGHT_SCALE: f32 = 10.0;
 
 #[derive(Component)]
 pub struct Chunk {
@@ -44,7 +49,9 @@ pub struct ChunkBundle {
 pub struct TerrainPlugin;
 impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, (initialize_world_generator, spawn_water).chain())
+        // Before the camera, which starts at the spawn
+        app.add_systems(PreStartup, initialize_world_generator)
+            .add_systems(Startup, spawn_water)
             .add_systems(
                 Update,
                 (
@@ -64,6 +71,16 @@ fn initialize_world_generator(mut commands: Commands) {
     commands.insert_resource(WorldGenRes(world_gen));
 }
 
+/// Position in the scene of the ground of a cell of the world
+pub fn cell_translation(world_gen: &WorldGen, (x, y): (isize, isize)) -> Vec3 {
+    let height = world_gen.generate_cell((x, y)).height as f32 * HEIGHT_SCALE;
+    Vec3::new(
+        x as f32 / RESOLUTION as f32,
+        height,
+        y as f32 / RESOLUTION as f32,
+    )
+}
+
 fn spawn_chunks_around_camera(
     mut commands: Commands,
     camera_query: Query<&Transform, With<MainCamera>>,
@@ -174,117 +191,22 @@ fn generate_terrain(
             terrain.position[0] * SIZE[0] as i32 * terrain.resolution as i32,
             terrain.position[1] * SIZE[1] as i32 * terrain.resolution as i32,
         ];
-        let cells = world_gen.0.generate_chunk(world_position, size);
-
-        // Collect unique biome types to build gradient
-        let mut biome_set = std::collections::HashSet::new();
-        for row in &cells {
-            for cell in row {
-                biome_set.insert(format!("{:?}", cell.biome));
-            }
-        }
-
-        let mut biomes: Vec<_> = cells
-            .iter()
-            .flat_map(|row| row.iter())
-            .map(|cell| &cell.biome)
-            .collect();
-        biomes.sort_by_key(|b| format!("{b:?}"));
-        biomes.dedup_by_key(|b| format!("{b:?}"));
-
-        let mut colors: Vec<colorgrad::Color> = Vec::with_capacity(biomes.len());
-        let mut domain: Vec<f32> = Vec::with_capacity(biomes.len());
-
-        for (i, biome) in biomes.iter().enumerate() {
-            let rgb = biome.color();
-            colors.push(colorgrad::Color {
-                r: f32::from(rgb[0]) / 255.0,
-                g: f32::from(rgb[1]) / 255.0,
-                b: f32::from(rgb[2]) / 255.0,
-                a: 1.0,
-            });
-            // Spread biomes evenly across 0-100 range
-            domain.push((i as f32 / (biomes.len() - 1).max(1) as f32) * 100.0);
-        }
-
-        let _grad = colorgrad::GradientBuilder::new()
-            .colors(&colors)
-            .domain(&domain)
-            .build::<colorgrad::LinearGradient>()
-            .unwrap_or_else(|_| {
-                colorgrad::GradientBuilder::new()
-                    .colors(&colors)
-                    .build::<colorgrad::LinearGradient>()
-                    .expect("Gradient generation failed")
-            });
-
-        let vertices_count: usize = ((size[0] + 1) * (size[1] + 1)) as usize;
-        let triangle_count: usize = (size[0] * size[1] * 2 * 3) as usize;
-
-        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(vertices_count);
-        let mut normals: Vec<[f32; 3]> = Vec::with_capacity(vertices_count);
-        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(vertices_count);
-        let mut indices: Vec<u32> = Vec::with_capacity(triangle_count);
-        let mut vertex_colors: Vec<[f32; 4]> = Vec::with_capacity(vertices_count);
-
-        let rows = size[0];
-        let cols = size[1];
-        for row in 0..rows {
-            for col in 0..cols {
-                let cell_y = col as usize;
-                let cell_x = row as usize;
-                let cell = &cells[cell_y][cell_x];
-
-                let height_value = cell.height as f32;
-
-                let x = terrain.position[0] as f32 * SIZE[0] as f32
-                    + row as f32 / terrain.resolution as f32;
-                let y = height_value * 10.0; // Scale up the height variation
-                let z = terrain.position[1] as f32 * SIZE[1] as f32
-                    + col as f32 / terrain.resolution as f32;
-
-                // Get color from biome
-                let rgb = cell.biome.color();
-                let color = [
-                    rgb[0] as f32 / 255.0,
-                    rgb[1] as f32 / 255.0,
-                    rgb[2] as f32 / 255.0,
-                    1.0,
-                ];
-
-                positions.push([x, y, z]);
-                normals.push([0.0, 1.0, 0.0]);
-                uvs.push([row as f32, col as f32]);
-                vertex_colors.push(color);
-            }
-        }
-
-        for i in 0..(rows - 1) {
-            for j in 0..(cols - 1) {
-                let current = i * cols + j;
-                let next_row = (i + 1) * cols + j;
-
-                // Triangle 1
-                indices.push(current);
-                indices.push(current + 1);
-                indices.push(next_row);
-
-                // Triangle 2
-                indices.push(next_row);
-                indices.push(current + 1);
-                indices.push(next_row + 1);
-            }
-        }
+        let settings = MeshSettings::default()
+            .set_origin(world_position)
+            .set_size(size)
+            .set_resolution(terrain.resolution)
+            .set_height_scale(HEIGHT_SCALE);
+        let terrain_mesh = TerrainMesh::generate(&world_gen.0, &settings);
 
         let mut mesh = Mesh::new(
             PrimitiveTopology::TriangleList,
             RenderAssetUsages::RENDER_WORLD,
         );
-        mesh.insert_indices(Indices::U32(indices.clone()));
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions.clone());
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, vertex_colors.clone());
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_indices(Indices::U32(terrain_mesh.indices));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, terrain_mesh.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, terrain_mesh.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, terrain_mesh.colors);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, terrain_mesh.uvs);
         *mesh_handle = Mesh3d(meshes.add(mesh));
 
         // Mark chunk as generated